@@ -0,0 +1,87 @@
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use std::{hint::black_box, num::NonZeroU32};
+use ultimate_tic_tac_toe::{
+    config::SearchConfig,
+    tree::{Tree, transposition::LookupBench},
+};
+
+/// whole searches, including selection, node insertion and playouts. Compare changes against a
+/// baseline with `cargo bench --bench mcts -- --save-baseline <name>` / `--baseline <name>`
+fn criterion_benchmark(c: &mut Criterion) {
+    let config = SearchConfig {
+        initial_n_nodes: 50_000,
+        ..SearchConfig::default()
+    };
+    c.bench_function("mcts 20k iterations from empty board", |b| {
+        b.iter_batched(
+            || Tree::new(&config),
+            |mut tree| {
+                tree.search_n(black_box(20_000));
+                tree
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    c.bench_function("mcts 20k iterations after compacting", |b| {
+        b.iter_batched(
+            || {
+                let mut tree = Tree::new(&config);
+                tree.search_n(20_000);
+                let best_move = tree.best_explored_move();
+                tree.apply_explored_move(best_move);
+                tree.compact();
+                tree
+            },
+            |mut tree| {
+                tree.search_n(black_box(20_000));
+                tree
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    // the same number of playouts as above, but a quarter of the selections
+    for reuse_simulation_buffer in [false, true] {
+        let config = SearchConfig {
+            playouts_per_leaf: NonZeroU32::new(4).unwrap(),
+            reuse_simulation_buffer,
+            ..config
+        };
+        c.bench_function(
+            &format!("mcts 5k iterations of 4 playouts, reused buffer: {reuse_simulation_buffer}"),
+            |b| {
+                b.iter_batched(
+                    || Tree::new(&config),
+                    |mut tree| {
+                        tree.search_n(black_box(5_000));
+                        tree
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+}
+
+/// the lookup of the tree on its own, against the `HashMap` it replaced
+fn lookup_benchmark(c: &mut Criterion) {
+    let bench = LookupBench::new(2_000, 11);
+    let mut group = c.benchmark_group("lookup of the positions of 2k random games");
+    // reported as lookups per second
+    group.throughput(Throughput::Elements(bench.n_positions() as u64));
+    group.bench_function("transposition table get or insert", |b| {
+        b.iter(|| black_box(&bench).table_get_or_insert())
+    });
+    group.bench_function("HashMap get or insert", |b| {
+        b.iter(|| black_box(&bench).hash_map_get_or_insert())
+    });
+    group.bench_function("transposition table probe", |b| {
+        b.iter(|| black_box(&bench).table_probe())
+    });
+    group.bench_function("HashMap probe", |b| {
+        b.iter(|| black_box(&bench).hash_map_probe())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark, lookup_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use ultimate_tic_tac_toe::{
+    rng::SearchRng,
+    tree::{
+        lane_simulation::{LANES, LaneSimulation},
+        simulation::SimulationState,
+    },
+};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let empty = SimulationState::empty();
+    let mut group = c.benchmark_group("random playouts from the empty board");
+    // reported as playouts per second
+    group.throughput(Throughput::Elements(LANES as u64));
+
+    let mut rng = SearchRng::default();
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for _ in 0..LANES {
+                black_box(black_box(empty).simulate_random(&mut rng));
+            }
+        })
+    });
+
+    let mut rngs: [SearchRng; LANES] =
+        std::array::from_fn(|lane| SearchRng::from_seed(lane as u64));
+    group.bench_function(format!("{LANES} lanes"), |b| {
+        b.iter(|| black_box(LaneSimulation::new(black_box(&empty)).simulate(&mut rngs)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,9 +1,9 @@
 use std::hint::black_box;
 
-use ultimate_tic_tac_toe::tree::TreePlayer1;
+use ultimate_tic_tac_toe::tree::Tree;
 
 fn main() {
-    let mut mcts_tree = TreePlayer1::new();
+    let mut mcts_tree = Tree::default();
 
     let n = 10;
 
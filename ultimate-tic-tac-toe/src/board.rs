@@ -96,7 +96,7 @@ impl Board {
 
     // NOTE PERF: avx/avx2 using 256bit registers have been evaluated but perform worse in this case
     // than the autovectorized code using `pand` `pcmpeqd` `packssdw` `pmovmskb`
-    fn calc_winner(&self) -> Option<Player> {
+    pub(crate) fn calc_winner(&self) -> Option<Player> {
         let mask_results = consts::WINNER_MASKS.map(|mask| mask & self.0);
         // NOTE PERF: this might look like multiple loops but it vectorizes much better
         // and there are NO loops in the generated assembly
@@ -24,11 +24,25 @@ impl OneBitBoard {
     pub const fn get(&self) -> BoardState {
         self.0
     }
+    pub const fn is_full(&self) -> bool {
+        self.0 == Self::MASK
+    }
+    /// # Returns
+    /// bitset over [`consts::WINNER_MASKS_1BIT`] of the lines without any set cell, e.g. the
+    /// super board lines a player can still complete if the set cells are the boards they can
+    /// no longer win
+    pub fn free_lines(&self) -> u8 {
+        consts::WINNER_MASKS_1BIT
+            .iter()
+            .enumerate()
+            .filter(|(_, mask)| *mask & self.0 == 0)
+            .fold(0, |lines, (line_idx, _)| lines | 1 << line_idx)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::board::one_bit::OneBitBoard;
+    use crate::{board::one_bit::OneBitBoard, consts};
 
     #[test]
     fn has_won_horizontal_top_row() {
@@ -74,4 +88,19 @@ mod test {
         let board = OneBitBoard::new(0b101_001_001);
         assert!(board.has_won());
     }
+
+    #[test]
+    fn free_lines_avoid_set_cells() {
+        assert_eq!(OneBitBoard::new(0).free_lines(), 0b1111_1111);
+        assert_eq!(OneBitBoard::new(0b1_1111_1111).free_lines(), 0);
+        // the centre lies on both diagonals, the middle row and the middle column
+        let free_lines = OneBitBoard::new(0b000_010_000).free_lines();
+        assert_eq!(free_lines.count_ones(), 4);
+        assert!(
+            consts::WINNER_MASKS_1BIT
+                .iter()
+                .enumerate()
+                .all(|(line_idx, mask)| (free_lines >> line_idx & 1 != 0) == (mask & 0b10000 == 0))
+        );
+    }
 }
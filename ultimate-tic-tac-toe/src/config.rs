@@ -0,0 +1,295 @@
+use std::{fmt, num::NonZeroU32, time::Duration};
+
+use crate::{engine::EngineKind, rng, tree::selection};
+
+/// Tunables of the search, [`SearchConfig::default`] matches what we play on codingame with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchConfig {
+    /// nodes the tree reserves space for up front, at most as many as fit into the memory
+    /// budget
+    pub initial_n_nodes: usize,
+    /// used to reserve space for the edges, pulled straight out of where the sun dont shine
+    pub guesstimate_avg_children: usize,
+    /// bytes the tree may occupy including reserved capacity and the solvers (split evenly
+    /// between the trees of a [`crate::tree::root_parallel::RootParallelTree`]), once reached
+    /// the search keeps running playouts from the leaves without growing the tree (see
+    /// [`crate::tree::Tree::is_degraded`])
+    pub memory_budget: usize,
+    pub exploration_c: selection::UCBScore,
+    /// subtracted from the turn times to account for IO and scheduling
+    pub timing_tolerance: Duration,
+    pub turn_time: Duration,
+    pub first_turn_time: Duration,
+    /// seeds the random stream each tree owns, searches with the same seed and number of
+    /// iterations are identical
+    pub seed: u64,
+    /// positions with at most this many empty cells are solved exactly instead of being played
+    /// out if the solve fits in the node budget of the solver, 0 disables the endgame solver
+    pub endgame_empty_cells: u32,
+    /// nodes the proof-number search may create when trying to prove the root, 0 disables it
+    pub proof_number_budget: usize,
+    /// how the move is chosen once the search is done
+    pub final_selection: selection::FinalSelection,
+    /// playouts from every new leaf, their mean is backpropagated as a single result
+    pub playouts_per_leaf: NonZeroU32,
+    /// runs the playouts of a leaf in one buffer instead of a fresh copy of the leaf each
+    pub reuse_simulation_buffer: bool,
+    /// plays the playouts of a leaf [`crate::tree::lane_simulation::LANES`] at a time in a
+    /// [`crate::tree::lane_simulation::LaneSimulation`], rounding `playouts_per_leaf` up to a
+    /// multiple of that, if the tree plays out uniformly at random
+    pub lane_playouts: bool,
+    /// the engine the binary plays with, the other fields apply to it as far as it uses them
+    pub engine: EngineKind,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            initial_n_nodes: 5_000_000,
+            guesstimate_avg_children: 30,
+            // codingame allows 768MB
+            memory_budget: 640 * 1024 * 1024,
+            exploration_c: selection::EXPLORATION_C,
+            timing_tolerance: Duration::from_millis(20),
+            turn_time: Duration::from_millis(100),
+            first_turn_time: Duration::from_secs(1),
+            seed: rng::DEFAULT_SEED,
+            // almost all of these are solved within the node budget of the solver, the median
+            // solve takes ~0.1ms
+            endgame_empty_cells: 12,
+            // ~15ms once used up, only roots that are likely to be proven are tried
+            proof_number_budget: 200_000,
+            final_selection: selection::FinalSelection::Robust,
+            playouts_per_leaf: NonZeroU32::MIN,
+            reuse_simulation_buffer: false,
+            lane_playouts: false,
+            engine: EngineKind::Mcts,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    InvalidValue { key: &'static str, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown flag {flag:?}"),
+            ConfigError::MissingValue(key) => write!(f, "missing value for {key}"),
+            ConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid value {value:?} for {key}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// (flag, environment variable) of every field
+const KEYS: [(&str, &str); 15] = [
+    ("--initial-n-nodes", "UTTT_INITIAL_N_NODES"),
+    ("--avg-children", "UTTT_AVG_CHILDREN"),
+    ("--memory-budget-mb", "UTTT_MEMORY_BUDGET_MB"),
+    ("--exploration-c", "UTTT_EXPLORATION_C"),
+    ("--timing-tolerance-ms", "UTTT_TIMING_TOLERANCE_MS"),
+    ("--turn-time-ms", "UTTT_TURN_TIME_MS"),
+    ("--first-turn-time-ms", "UTTT_FIRST_TURN_TIME_MS"),
+    ("--seed", "UTTT_SEED"),
+    ("--endgame-empty-cells", "UTTT_ENDGAME_EMPTY_CELLS"),
+    ("--pn-node-budget", "UTTT_PN_NODE_BUDGET"),
+    ("--final-selection", "UTTT_FINAL_SELECTION"),
+    ("--playouts-per-leaf", "UTTT_PLAYOUTS_PER_LEAF"),
+    ("--reuse-simulation-buffer", "UTTT_REUSE_SIMULATION_BUFFER"),
+    ("--lane-playouts", "UTTT_LANE_PLAYOUTS"),
+    ("--engine", "UTTT_ENGINE"),
+];
+
+impl SearchConfig {
+    /// time to search for a regular turn
+    pub fn turn_budget(&self) -> Duration {
+        self.turn_time.saturating_sub(self.timing_tolerance)
+    }
+
+    /// time to search for our first turn
+    pub fn first_turn_budget(&self) -> Duration {
+        self.first_turn_time.saturating_sub(self.timing_tolerance)
+    }
+
+    /// Starts from the defaults, then applies the environment variables and lastly the flags
+    /// (`--turn-time-ms 100` or `--turn-time-ms=100`).
+    /// # Arguments
+    /// - `args`: the command line arguments without the program name
+    /// - `env_var`: looks up an environment variable, e.g. `|key| std::env::var(key).ok()`
+    pub fn from_args_and_env(
+        args: impl IntoIterator<Item = String>,
+        env_var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+
+        for (flag, env_key) in KEYS {
+            if let Some(value) = env_var(env_key) {
+                config.set(flag, &value)?;
+            }
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_owned(), Some(value.to_owned())),
+                None => (arg, None),
+            };
+            let Some(&(flag, _)) = KEYS.iter().find(|(known, _)| *known == flag) else {
+                return Err(ConfigError::UnknownFlag(flag));
+            };
+            let value = inline_value
+                .or_else(|| args.next())
+                .ok_or(ConfigError::MissingValue(flag))?;
+            config.set(flag, &value)?;
+        }
+
+        Ok(config)
+    }
+
+    fn set(&mut self, flag: &'static str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            key: flag,
+            value: value.to_owned(),
+        };
+        let millis = || {
+            value
+                .parse()
+                .map(Duration::from_millis)
+                .map_err(|_| invalid())
+        };
+
+        match flag {
+            "--initial-n-nodes" => self.initial_n_nodes = value.parse().map_err(|_| invalid())?,
+            "--avg-children" => {
+                self.guesstimate_avg_children = value.parse().map_err(|_| invalid())?
+            }
+            "--memory-budget-mb" => {
+                self.memory_budget = value
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|mb| mb.checked_mul(1024 * 1024))
+                    .ok_or_else(invalid)?
+            }
+            "--exploration-c" => self.exploration_c = value.parse().map_err(|_| invalid())?,
+            "--timing-tolerance-ms" => self.timing_tolerance = millis()?,
+            "--turn-time-ms" => self.turn_time = millis()?,
+            "--first-turn-time-ms" => self.first_turn_time = millis()?,
+            "--seed" => self.seed = value.parse().map_err(|_| invalid())?,
+            "--endgame-empty-cells" => {
+                self.endgame_empty_cells = value.parse().map_err(|_| invalid())?
+            }
+            "--pn-node-budget" => {
+                self.proof_number_budget = value.parse().map_err(|_| invalid())?
+            }
+            "--final-selection" => {
+                self.final_selection =
+                    selection::FinalSelection::from_name(value).ok_or_else(invalid)?
+            }
+            "--playouts-per-leaf" => {
+                self.playouts_per_leaf = value.parse().map_err(|_| invalid())?
+            }
+            "--reuse-simulation-buffer" => {
+                self.reuse_simulation_buffer = value.parse().map_err(|_| invalid())?
+            }
+            "--lane-playouts" => self.lane_playouts = value.parse().map_err(|_| invalid())?,
+            "--engine" => self.engine = EngineKind::from_name(value).ok_or_else(invalid)?,
+            _ => unreachable!("all flags in KEYS must be handled"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        config::{ConfigError, SearchConfig},
+        engine::EngineKind,
+        tree::selection::FinalSelection,
+    };
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<SearchConfig, ConfigError> {
+        SearchConfig::from_args_and_env(args.iter().map(|arg| arg.to_string()), |key| {
+            env.iter()
+                .find(|(env_key, _)| *env_key == key)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn defaults_without_args_or_env() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config, SearchConfig::default());
+        assert_eq!(config.turn_budget(), Duration::from_millis(80));
+        assert_eq!(config.first_turn_budget(), Duration::from_millis(980));
+    }
+
+    #[test]
+    fn flags_override_env() {
+        let config = parse(
+            &[
+                "--turn-time-ms",
+                "150",
+                "--seed=42",
+                "--memory-budget-mb",
+                "64",
+                "--final-selection=robust-max",
+                "--engine",
+                "alpha-beta",
+            ],
+            &[
+                ("UTTT_TURN_TIME_MS", "120"),
+                ("UTTT_EXPLORATION_C", "0.5"),
+                ("UTTT_ENGINE", "board"),
+                ("UTTT_LANE_PLAYOUTS", "true"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.turn_time, Duration::from_millis(150));
+        assert_eq!(config.seed, 42);
+        assert_eq!(config.memory_budget, 64 * 1024 * 1024);
+        assert_eq!(config.final_selection, FinalSelection::RobustMax);
+        assert_eq!(config.engine, EngineKind::AlphaBeta);
+        assert!(config.lane_playouts);
+        assert_eq!(config.exploration_c, 0.5);
+        assert_eq!(
+            config.initial_n_nodes,
+            SearchConfig::default().initial_n_nodes
+        );
+    }
+
+    #[test]
+    fn reports_bad_input() {
+        assert_eq!(
+            parse(&["--turn-time"], &[]),
+            Err(ConfigError::UnknownFlag("--turn-time".to_owned()))
+        );
+        assert_eq!(
+            parse(&["--seed"], &[]),
+            Err(ConfigError::MissingValue("--seed"))
+        );
+        assert_eq!(
+            parse(&[], &[("UTTT_AVG_CHILDREN", "many")]),
+            Err(ConfigError::InvalidValue {
+                key: "--avg-children",
+                value: "many".to_owned()
+            })
+        );
+        assert_eq!(
+            parse(&["--engine=minimax"], &[]),
+            Err(ConfigError::InvalidValue {
+                key: "--engine",
+                value: "minimax".to_owned()
+            })
+        );
+    }
+}
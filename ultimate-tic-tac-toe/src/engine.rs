@@ -0,0 +1,261 @@
+use std::time::Instant;
+
+use crate::{
+    board::{Board, move_finder::BoardMoveFinder},
+    config::SearchConfig,
+    tree::{Tree, alpha_beta::AlphaBetaEngine, time_manager::TimeManager},
+    types::{Index, Player},
+    util,
+};
+
+/// (row, col) of a cell like in the codingame protocol, 0..3 on a single board and 0..9 on the
+/// nested board
+pub type GridMove = (u8, u8);
+
+/// What the binary, tests and match runners need from a bot, regardless of how it searches.
+///
+/// A game is driven as:
+/// 1. [`Engine::new_game`]
+/// 2. [`Engine::apply_opponent_move`] unless we move first
+/// 3. [`Engine::think`] (or [`Engine::think_until`]) and [`Engine::choose_move`] for our move
+/// 4. [`Engine::ponder`] while the opponent thinks, continue at 2.
+pub trait Engine {
+    /// forgets the previous game, `my_player` is [`Player::Player1`] if we move first
+    fn new_game(&mut self, my_player: Player);
+    fn apply_opponent_move(&mut self, opponent_move: GridMove);
+    /// uses the time until `deadline` to search for our move
+    fn think_until(&mut self, deadline: Instant);
+    /// Searches for our move within the turn of the time manager. By default the engine thinks
+    /// until the soft deadline, see [`TimeManager`].
+    fn think(&mut self, time_manager: &TimeManager) {
+        self.think_until(time_manager.soft_deadline());
+    }
+    /// picks our move and plays it
+    fn choose_move(&mut self) -> GridMove;
+    /// Called while the opponent is thinking, keeps thinking until `stop` returns true.
+    /// By default the engine does not think on the opponent's time.
+    fn ponder(&mut self, _stop: &mut dyn FnMut() -> bool) {}
+}
+
+/// Which [`Engine`] the binary plays with, see [`SearchConfig::engine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EngineKind {
+    /// [`MctsEngine`]
+    #[default]
+    Mcts,
+    /// [`AlphaBetaEngine`]
+    AlphaBeta,
+    /// [`BoardEngine`], plays a single board instead of the nested one
+    Board,
+}
+
+impl EngineKind {
+    /// `mcts`, `alpha-beta` or `board`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mcts" => Some(EngineKind::Mcts),
+            "alpha-beta" => Some(EngineKind::AlphaBeta),
+            "board" => Some(EngineKind::Board),
+            _ => None,
+        }
+    }
+}
+
+/// the exact solver for a single tic-tac-toe board, see [`Board::find_best_move`]
+#[derive(Debug, Clone, Copy)]
+pub struct BoardEngine {
+    board: Board,
+    my_player: Player,
+    move_finder: BoardMoveFinder,
+}
+
+impl Default for BoardEngine {
+    fn default() -> Self {
+        Self {
+            board: Board::new(),
+            my_player: Player::Player1,
+            move_finder: BoardMoveFinder::default(),
+        }
+    }
+}
+
+impl Engine for BoardEngine {
+    fn new_game(&mut self, my_player: Player) {
+        *self = Self {
+            my_player,
+            ..Self::default()
+        };
+    }
+
+    fn apply_opponent_move(&mut self, (row, col): GridMove) {
+        self.board
+            .set(row as Index, col as Index, self.my_player.other());
+    }
+
+    /// the board is solved exactly when choosing the move, there is nothing to think about
+    fn think_until(&mut self, _deadline: Instant) {}
+
+    fn choose_move(&mut self) -> GridMove {
+        let (row, col) = Board::to_2d_idx(
+            self.board
+                .find_best_move(self.my_player, &mut self.move_finder),
+        );
+        self.board.set(row, col, self.my_player);
+        (row as u8, col as u8)
+    }
+}
+
+/// the Monte Carlo tree search of [`Tree`], which plays for whoever is to move at its root
+pub struct MctsEngine {
+    config: SearchConfig,
+    tree: Tree,
+}
+
+impl MctsEngine {
+    pub fn new(config: &SearchConfig) -> Self {
+        Self {
+            config: *config,
+            tree: Tree::new(config),
+        }
+    }
+}
+
+impl Engine for MctsEngine {
+    /// the tree always starts at the empty board, it does not need to know which side we play
+    fn new_game(&mut self, _my_player: Player) {
+        self.tree = Tree::new(&self.config);
+    }
+
+    fn apply_opponent_move(&mut self, (row, col): GridMove) {
+        self.tree
+            .apply_maybe_explored_move(util::to_board_col_major_move(row, col));
+    }
+
+    fn think_until(&mut self, deadline: Instant) {
+        self.tree.search_until(deadline);
+        // the search does nothing once the deadline passed, but there must be a move to choose
+        if self.tree.root_move_stats().is_empty() {
+            self.tree.search_n(1);
+        }
+    }
+
+    /// stops as soon as the move is settled, see [`Tree::search_timed`]
+    fn think(&mut self, time_manager: &TimeManager) {
+        self.tree.search_timed(time_manager);
+    }
+
+    fn choose_move(&mut self) -> GridMove {
+        let best_move = self.tree.best_explored_move();
+        self.tree.apply_explored_move(best_move);
+        util::board_col_major_move_to_2d(best_move)
+    }
+
+    /// cleans up the abandoned branches first, then searches until `stop` says so
+    fn ponder(&mut self, stop: &mut dyn FnMut() -> bool) {
+        self.tree.compact();
+        self.tree.ponder(|| stop().then_some(()));
+    }
+}
+
+impl Engine for AlphaBetaEngine {
+    fn new_game(&mut self, _my_player: Player) {
+        self.restart();
+    }
+
+    fn apply_opponent_move(&mut self, (row, col): GridMove) {
+        self.apply_move(util::to_board_col_major_move(row, col));
+    }
+
+    fn think_until(&mut self, deadline: Instant) {
+        self.search_until(deadline);
+    }
+
+    fn choose_move(&mut self) -> GridMove {
+        let best_move = self.best_move();
+        self.apply_move(best_move);
+        util::board_col_major_move_to_2d(best_move)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        board::Board,
+        config::SearchConfig,
+        engine::{BoardEngine, Engine, MctsEngine},
+        tree::{alpha_beta::AlphaBetaEngine, simulation::SimulationState},
+        types::{CellState, Index, Player},
+        util,
+    };
+
+    /// plays a game of ultimate tic-tac-toe between the engines, checking every move
+    /// # Returns
+    /// the number of moves until the game ended
+    fn play_game<'a>(
+        player1: &'a mut dyn Engine,
+        player2: &'a mut dyn Engine,
+        time_per_move: Duration,
+    ) -> usize {
+        player1.new_game(Player::Player1);
+        player2.new_game(Player::Player2);
+        let mut referee = SimulationState::empty();
+        let mut engines = [player1, player2];
+        for n_moves in 1.. {
+            let [to_move, waiting] = &mut engines;
+            to_move.think_until(Instant::now() + time_per_move);
+            let (row, col) = to_move.choose_move();
+            let move_ = util::to_board_col_major_move(row, col);
+            assert!(
+                referee.available_in_board_or_fallback().get() & (1 << move_) != 0,
+                "illegal move {move_}"
+            );
+            let has_won;
+            (referee, has_won) = referee.apply_move(move_);
+            if has_won || referee.all_boards_decided() {
+                return n_moves;
+            }
+            waiting.apply_opponent_move((row, col));
+            engines.swap(0, 1);
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn engines_play_each_other() {
+        let config = SearchConfig {
+            initial_n_nodes: 10_000,
+            ..SearchConfig::default()
+        };
+        let (mut mcts, mut alpha_beta) = (MctsEngine::new(&config), AlphaBetaEngine::new());
+        let n_moves = play_game(&mut mcts, &mut alpha_beta, Duration::from_millis(5));
+        assert!(n_moves >= 17);
+        // and again with switched sides on the same engines
+        let n_moves = play_game(&mut alpha_beta, &mut mcts, Duration::from_millis(5));
+        assert!(n_moves >= 17);
+    }
+
+    #[test]
+    fn board_engines_play_a_legal_game() {
+        let (mut player1, mut player2) = (BoardEngine::default(), BoardEngine::default());
+        player1.new_game(Player::Player1);
+        player2.new_game(Player::Player2);
+        let mut referee = Board::new();
+        for move_idx in 0..9 {
+            let (to_move, waiting, player) = if move_idx % 2 == 0 {
+                (&mut player1, &mut player2, Player::Player1)
+            } else {
+                (&mut player2, &mut player1, Player::Player2)
+            };
+            to_move.think_until(Instant::now());
+            let (row, col) = to_move.choose_move();
+            assert_eq!(referee.get(row as Index, col as Index), CellState::Free);
+            referee.set(row as Index, col as Index, player);
+            if referee.calc_winner().is_some() {
+                break;
+            }
+            waiting.apply_opponent_move((row, col));
+        }
+    }
+}
@@ -1,7 +1,9 @@
 mod bitmagic;
 pub mod board;
+pub mod config;
 pub mod consts;
-mod rng;
+pub mod engine;
+pub mod rng;
 pub mod tree;
 pub mod types;
 pub mod util;
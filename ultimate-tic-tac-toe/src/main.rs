@@ -2,61 +2,16 @@ use std::{
     io::BufRead,
     sync::mpsc::{self},
     thread,
-    time::{Duration, Instant},
+    time::Instant,
 };
 
 use ultimate_tic_tac_toe::{
-    board::{Board, move_finder::BoardMoveFinder},
-    tree::{TreeForPlayer, TreePlayer1, TreePlayer2},
-    types::{Index, PLAYER1_U8, PLAYER2_U8, Player, PlayerU8},
-    util,
+    config::SearchConfig,
+    engine::{BoardEngine, Engine, EngineKind, MctsEngine},
+    tree::{alpha_beta::AlphaBetaEngine, time_manager::TimeManager},
+    types::Player,
 };
 
-#[allow(unused)]
-fn run_v1() {
-    let mut board = Board::new();
-    let mut input = String::new();
-    let read_line_buffered = |buf: &mut String| {
-        buf.clear();
-        std::io::stdin().read_line(buf).unwrap();
-    };
-    let mut my_player = Player::Player2;
-
-    let move_calc = &mut BoardMoveFinder::default();
-
-    loop {
-        read_line_buffered(&mut input);
-        let (opp_row, opp_col) = input
-            .trim_end()
-            .split_once(' ')
-            .expect("opponent input should have a space");
-        let (opp_row, opp_col) = (
-            opp_row.parse::<i32>().expect("opp_row is not usize"),
-            opp_col.parse::<i32>().expect("opp_col is not usize"),
-        );
-
-        // read and discard available inputs
-        read_line_buffered(&mut input);
-        let n_available = input
-            .trim_end()
-            .parse::<usize>()
-            .expect("n_available is not a usize");
-        for _ in 0..n_available {
-            read_line_buffered(&mut input);
-        }
-
-        if opp_row == -1 {
-            my_player = Player::Player1;
-        } else {
-            board.set(opp_row as Index, opp_col as Index, my_player.other());
-        }
-
-        let (row, col) = Board::to_2d_idx(board.find_best_move(my_player, move_calc));
-        board.set(row, col, my_player);
-        println!("{row} {col}");
-    }
-}
-
 fn spawn_stdin_channel() -> mpsc::Receiver<String> {
     let (tx, rx) = mpsc::channel::<String>();
     thread::spawn(move || {
@@ -70,14 +25,6 @@ fn spawn_stdin_channel() -> mpsc::Receiver<String> {
     rx
 }
 
-const TIMING_TOLERANCE: Duration = Duration::from_millis(20);
-const FIRST_TURN_TIME: Duration = Duration::from_secs(1)
-    .checked_sub(TIMING_TOLERANCE)
-    .unwrap();
-const TURN_TIME: Duration = Duration::from_millis(100)
-    .checked_sub(TIMING_TOLERANCE)
-    .unwrap();
-
 fn read_and_ignore_available(input_rx: &mpsc::Receiver<String>) {
     // read and discard available inputs
     let input = input_rx.recv().expect("failed to read n_available");
@@ -91,83 +38,60 @@ fn read_and_ignore_available(input_rx: &mpsc::Receiver<String>) {
     }
 }
 
-fn run_v2_on_initialized_tree<const SCORE_IN_FAVOR_OF: PlayerU8>(
-    mut tree: TreeForPlayer<SCORE_IN_FAVOR_OF>,
-    input_rx: mpsc::Receiver<String>,
-) {
-    // try to be cheeky and calculate while the other person is doing their turn
-    #[expect(unused)]
-    let calc_while_read_input = |tree: &mut TreeForPlayer<SCORE_IN_FAVOR_OF>| {
-        loop {
-            match input_rx.try_recv() {
-                Ok(input) => break input,
-                Err(mpsc::TryRecvError::Empty) => {
-                    tree.search_n(1);
-                }
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    panic!("input channel closed")
-                }
-            }
-        }
-    };
-    let mut best_move = tree.best_explored_move();
-    tree.apply_explored_move(best_move);
-
-    let (row, col) = util::board_col_major_move_to_2d(best_move);
-    println!("{row} {col}");
-
-    loop {
-        // TODO MERBUG: re-enable cheeky calcing but fix perspective
-        // let input = calc_while_read_input(&mut tree);
-        let input = input_rx.recv().expect("failed to read opponent move");
-        let (opp_row, opp_col) = input
-            .trim_end()
-            .split_once(' ')
-            .expect("opponent input should have a space {input:?}");
-        let (opp_row, opp_col) = (
-            opp_row.parse::<u8>().expect("opp_row is not u8"),
-            opp_col.parse::<u8>().expect("opp_col is not u8"),
-        );
-
-        read_and_ignore_available(&input_rx);
-        let turn_start = Instant::now();
-
-        let opp_board_col_major_move = util::to_board_col_major_move(opp_row, opp_col);
-        tree.apply_explored_move(opp_board_col_major_move);
-        tree.search_until(turn_start + TURN_TIME);
-        best_move = tree.best_explored_move();
-        tree.apply_explored_move(best_move);
-
-        let (row, col) = util::board_col_major_move_to_2d(best_move);
-        println!("{row} {col}");
-    }
-}
-
-fn run_v2() {
-    let input_rx = spawn_stdin_channel();
-
-    let first_input = input_rx.recv().expect("failed to get first input");
-    let (opp_row, opp_col) = first_input
+/// "row col" of the opponent's move, -1 -1 if we move first
+fn parse_opponent_move(input: &str) -> (i32, i32) {
+    let (opp_row, opp_col) = input
         .trim_end()
         .split_once(' ')
-        .expect("opponent input should have a space");
-    let (opp_row, opp_col) = (
+        .expect("opponent input should have a space {input:?}");
+    (
         opp_row.parse::<i32>().expect("opp_row is not i32"),
         opp_col.parse::<i32>().expect("opp_col is not i32"),
-    );
+    )
+}
+
+/// Plays one game over stdin/stdout with any engine, e.g. [`MctsEngine`] or the single board
+/// [`BoardEngine`].
+fn run<E: Engine>(config: &SearchConfig, engine: &mut E) {
+    let input_rx = spawn_stdin_channel();
 
+    let first_input = input_rx.recv().expect("failed to get first input");
+    // the turn timer runs from the first line of the turn, not from when we are done reading
+    let time_manager = TimeManager::new(Instant::now(), config.first_turn_budget());
+    let (opp_row, opp_col) = parse_opponent_move(&first_input);
     read_and_ignore_available(&input_rx);
-    let initial_start_time = Instant::now();
-    let inital_end_time = initial_start_time + FIRST_TURN_TIME;
     if opp_row == -1 {
-        let mut tree: TreePlayer1 = TreePlayer1::new();
-        tree.search_until(inital_end_time);
-        run_v2_on_initialized_tree::<PLAYER1_U8>(tree, input_rx);
+        engine.new_game(Player::Player1);
     } else {
-        let board_idx = util::to_board_col_major_move(opp_row as u8, opp_col as u8);
-        let mut tree: TreePlayer2 = TreePlayer2::new(board_idx);
-        tree.search_until(inital_end_time);
-        run_v2_on_initialized_tree::<PLAYER2_U8>(tree, input_rx);
+        engine.new_game(Player::Player2);
+        engine.apply_opponent_move((opp_row as u8, opp_col as u8));
+    }
+    engine.think(&time_manager);
+
+    loop {
+        let (row, col) = engine.choose_move();
+        println!("{row} {col}");
+
+        // be cheeky and calculate while the other person is doing their turn
+        let mut input = None;
+        engine.ponder(&mut || match input_rx.try_recv() {
+            Ok(received) => {
+                input = Some((received, Instant::now()));
+                true
+            }
+            Err(mpsc::TryRecvError::Empty) => false,
+            Err(mpsc::TryRecvError::Disconnected) => panic!("input channel closed"),
+        });
+        let (input, input_read) = input.unwrap_or_else(|| {
+            let received = input_rx.recv().expect("input channel closed");
+            (received, Instant::now())
+        });
+        let time_manager = TimeManager::new(input_read, config.turn_budget());
+        let (opp_row, opp_col) = parse_opponent_move(&input);
+
+        read_and_ignore_available(&input_rx);
+        engine.apply_opponent_move((opp_row as u8, opp_col as u8));
+        engine.think(&time_manager);
     }
 }
 
@@ -181,5 +105,17 @@ fn main() {
         assert!(std::is_x86_feature_detected!("avx2"));
     }
 
-    run_v2();
+    // flags take precedence over environment variables, see `SearchConfig::from_args_and_env`
+    let config =
+        SearchConfig::from_args_and_env(std::env::args().skip(1), |key| std::env::var(key).ok())
+            .unwrap_or_else(|err| panic!("invalid search config: {err}"));
+
+    match config.engine {
+        EngineKind::Mcts => run(&config, &mut MctsEngine::new(&config)),
+        EngineKind::AlphaBeta => run(
+            &config,
+            &mut AlphaBetaEngine::with_memory_budget(config.memory_budget),
+        ),
+        EngineKind::Board => run(&config, &mut BoardEngine::default()),
+    }
 }
@@ -1,24 +1,34 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::SmallRng};
 
-mod internal {
-    use crate::consts;
-    use rand::SeedableRng;
+/// see [`crate::config::SearchConfig::seed`]
+pub(crate) const DEFAULT_SEED: u64 = 0xfeebdaed_deadbeef;
 
-    const SEED: u64 = 0xfeebdaed_deadbeef;
-    use std::cell::UnsafeCell;
-    thread_local! {
-        static MOVE_DISTRIBUTION: rand::distr::Uniform<u8> = rand::distr::Uniform::new_inclusive(0, consts::N_CELLS_NESTED as u8 - 1).unwrap();
-        static RNG: UnsafeCell<rand::rngs::SmallRng> = UnsafeCell::new(rand::rngs::SmallRng::seed_from_u64(SEED))
+/// The random number generator of a single search. Every tree (and every worker of a parallel
+/// search) owns one and passes it down to its playouts, so a search only depends on its own seed
+/// and not on whatever else runs on the same thread.
+#[derive(Debug, Clone)]
+pub struct SearchRng(SmallRng);
+
+impl Default for SearchRng {
+    fn default() -> Self {
+        Self::from_seed(DEFAULT_SEED)
+    }
+}
+
+impl SearchRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self(SmallRng::seed_from_u64(seed))
     }
 
-    /// it is forbidden, and impossible in safe rust, to smuggle a (mutable) reference to the rng
-    /// out of this function
-    pub fn do_with_rng<T, F: FnOnce(&mut rand::rngs::SmallRng) -> T>(f: F) -> T {
-        // safety: mutable references to RNG can't escape anywhere and is only read temporarily
-        RNG.with(|rng| f(unsafe { rng.get().as_mut().unwrap() }))
+    pub fn rand_in_move_range_exclusive(&mut self, max_exclusive: u8) -> u8 {
+        self.0.random_range(0..max_exclusive)
     }
 }
 
-pub fn rand_in_move_range_exclusive(max_exclusive: u8) -> u8 {
-    internal::do_with_rng(|rng| rng.random_range(0..max_exclusive))
+/// derives the seed of an independent stream from the base seed (splitmix64)
+pub fn stream_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add((stream + 1).wrapping_mul(0x9e3779b9_7f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d_1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb_133111eb);
+    z ^ (z >> 31)
 }
@@ -1,36 +1,112 @@
 use std::{
-    collections::{HashMap, hash_map::Entry},
     iter,
-    num::NonZero,
+    num::{NonZero, NonZeroU32},
     sync::atomic::AtomicBool,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use crate::{
     bitmagic,
+    config::SearchConfig,
     consts::{self},
-    rng,
-    tree::node_state::NodeState,
-    types::{PLAYER1_U8, PLAYER2_U8, Player, PlayerU8},
+    rng::{self, SearchRng},
+    tree::{
+        endgame::EndgameSolver,
+        lane_simulation::{LANES, LaneSimulation},
+        node_state::NodeState,
+        proof_number::{ProofNumberResult, ProofNumberSearch},
+        selection::{ChildStats, FinalSelection, SelectionPolicy, UCBScore, Ucb1},
+        simulation::{RolloutPolicy, SimulationState, UniformRollout},
+        stats::LastSearch,
+        time_manager::{SearchProgress, TimeManager},
+        transposition::{Probe, TranspositionTable},
+    },
+    types::Player,
 };
 
+pub mod alpha_beta;
+mod endgame;
+pub mod export;
+pub mod lane_simulation;
 mod node_state;
-mod simulation;
+mod proof_number;
+pub mod root_parallel;
+pub mod selection;
+pub mod shared;
+pub mod simulation;
+pub mod stats;
+pub mod time_manager;
+pub mod transposition;
 
 type NodeIdx = u32;
 
 type MonteCarloScore = i32;
 const NO_MOVE_FORCED: u8 = 9;
 
+/// game theoretic value of a node, in favor of the player to move at the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(i8)]
+enum Proof {
+    Loss = -1,
+    Draw = 0,
+    Win = 1,
+}
+
+impl Proof {
+    const fn from_score(score: MonteCarloScore) -> Self {
+        match score.signum() {
+            -1 => Proof::Loss,
+            0 => Proof::Draw,
+            _ => Proof::Win,
+        }
+    }
+    const fn score(self) -> MonteCarloScore {
+        self as MonteCarloScore
+    }
+    /// the same result from the perspective of the other player
+    const fn flipped(self) -> Self {
+        match self {
+            Proof::Loss => Proof::Win,
+            Proof::Draw => Proof::Draw,
+            Proof::Win => Proof::Loss,
+        }
+    }
+}
+
 /// NOTE: Node::default() is not a valid node and more of a placeholder
+///
+/// Nodes are shared between all parents reaching the same state (transpositions), so the
+/// statistics of a node only describe the node itself, how often a parent chose it is tracked
+/// per [`Edge`].
 #[derive(Debug, Clone, Copy, Default)]
 struct Node {
     game_state: NodeState,
+    /// playouts started from this node plus the visits of all of its edges
     visits: u32,
-    score: MonteCarloScore,
+    /// mean result of the visits, in favor of the player to move at the node:
+    /// the node's own playouts mixed with the negated values of its children weighted by edge
+    /// visits
+    value: f32,
+    /// the same mix of the squared results, for variance estimates
+    value_squared: f32,
+    /// playouts started from this node itself (or visits of the proven node), a batch of
+    /// [`SearchConfig::playouts_per_leaf`] counts once with its mean result
+    playout_visits: u32,
+    playout_score: f32,
+    playout_score_squared: f32,
     /// first child node at `first_edge + 1`
     first_edge: NodeIdx,
     child_count: u8, // <= N_CELLS_NESTED
+    /// known result under perfect play, always set for terminal nodes
+    proof: Option<Proof>,
+}
+
+impl Node {
+    fn add_playout(&mut self, score: f32) {
+        self.playout_visits += 1;
+        self.playout_score += score;
+        self.playout_score_squared += score * score;
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -38,81 +114,109 @@ struct Edge {
     /// nonzero because arriving back at an empty board would make no sense
     /// (also allows niche optimization with Option<NonZero>)
     child_node: Option<NonZero<NodeIdx>>,
+    /// how often the parent chose this edge, <= the visits of the child
+    visits: u32,
     move_: u8,
 }
 
-pub struct TreeForPlayer<const SCORE_IN_FAVOR_OF: PlayerU8> {
+/// Monte Carlo graph search over the positions reachable from the root.
+///
+/// Scores are stored negamax style, every node in favor of the player to move at it, so the same
+/// tree answers for whichever player is to move at the root, e.g. both sides in self-play.
+pub struct Tree<S: SelectionPolicy = Ucb1, R: RolloutPolicy = UniformRollout> {
     root: NodeIdx,
     // TODO PERF: maybe try to get this automatically promoted to a huge page by alignment
     nodes: Vec<Node>,
     edges: Vec<Edge>,
-    // TODO PERF: std lib hash function is probably sub optimal because of hashDoS mitigations
-    lookup_without_root: HashMap<NodeState, NodeIdx>,
+    lookup_without_root: TranspositionTable,
     edge_selection_buf: [NodeIdx; consts::N_CELLS_NESTED as usize],
+    selection_policy: S,
+    rollout_policy: R,
+    /// see [`SearchConfig::memory_budget`]
+    memory_budget: usize,
+    /// set once the tree stopped growing because of the memory budget
+    degraded: bool,
+    last_search: LastSearch,
+    /// the deadline of the running search if it has one, bounds the [`EndgameSolver`]
+    deadline: Option<Instant>,
+    /// proves new nodes close to the end of the game, see [`SearchConfig::endgame_empty_cells`]
+    endgame_solver: EndgameSolver,
+    /// tries to prove the root once per root, see [`SearchConfig::proof_number_budget`]
+    proof_number_search: ProofNumberSearch,
+    root_proof_attempted: bool,
+    /// see [`SearchConfig::final_selection`]
+    final_selection: FinalSelection,
+    /// seeded with [`SearchConfig::seed`], drives the expansion order and the playouts
+    rng: SearchRng,
+    /// see [`SearchConfig::playouts_per_leaf`]
+    playouts_per_leaf: NonZeroU32,
+    /// see [`SearchConfig::reuse_simulation_buffer`]
+    reuse_simulation_buffer: bool,
+    simulation_buffer: SimulationState,
+    /// see [`SearchConfig::lane_playouts`]
+    lane_playouts: bool,
+    /// derived from the seed of [`Self::rng`], one per lane of a [`LaneSimulation`]
+    lane_rngs: [SearchRng; LANES],
 }
 
-pub type TreePlayer1 = TreeForPlayer<PLAYER1_U8>;
-pub type TreePlayer2 = TreeForPlayer<PLAYER2_U8>;
-
-impl Default for TreePlayer1 {
-    fn default() -> TreePlayer1 {
-        Self::new()
+impl Default for Tree {
+    fn default() -> Tree {
+        Self::new(&SearchConfig::default())
     }
 }
 
-impl TreePlayer1 {
-    pub fn new() -> Self {
-        let nodes = Vec::with_capacity(Self::INITIAL_N_NODES);
-        let edges = Vec::with_capacity(Self::INITIAL_N_NODES * Self::GUESSTIMATE_AVG_CHILDREN);
-
-        let lookup_without_root = HashMap::with_capacity(Self::INITIAL_N_NODES);
-
-        let mut this = Self {
-            root: 0,
-            nodes,
-            edges,
-            lookup_without_root,
-            edge_selection_buf: [0; consts::N_CELLS_NESTED as usize],
-        };
-
-        this.insert_root_node();
-
-        this
+impl Tree {
+    /// a tree for the empty board, player 1 to move
+    pub fn new(config: &SearchConfig) -> Self {
+        Self::new_with_policy(config, Ucb1::from_config(config))
     }
-    fn insert_root_node(&mut self) -> NodeIdx {
-        debug_assert_eq!(self.nodes.len(), 0);
-        debug_assert_eq!(self.edges.len(), 0);
-        let idx = 0;
-
-        let node_state = NodeState::empty();
-
-        let available_children = node_state.available_in_board_or_fallback();
-        let child_count = bitmagic::count_ones_u128(available_children.get()) as u8;
-
-        let first_edge = 0;
-        // TODO PERF check how well this optimizes (should essentially just advance the len) since the vec is zeroed anyways
-        self.edges
-            .extend(iter::repeat_n(Edge::default(), child_count as usize));
-
-        self.nodes.push(Node {
-            game_state: node_state,
-            visits: 0,
-            score: 0,
-            child_count,
-            first_edge,
-        });
-
-        idx
-        // no need to add this to the lookup, empty state can not be reused as its impossible
-        // to clear occupied cells
+    /// see [`Tree::from_moves_with_policy`]
+    pub fn from_moves(config: &SearchConfig, moves: &[u8]) -> Self {
+        Self::from_moves_with_policy(config, moves, Ucb1::from_config(config))
+    }
+}
+impl<S: SelectionPolicy> Tree<S> {
+    pub fn new_with_policy(config: &SearchConfig, selection_policy: S) -> Self {
+        Self::with_root_state(config, NodeState::empty(), selection_policy)
+    }
+    /// A tree for the position after playing `moves` (col-major board moves) from the empty
+    /// board, e.g. `&[move_by_player1]` for player 2's first turn.
+    /// # Panics
+    /// if a move is not available or the game is over after the moves
+    pub fn from_moves_with_policy(
+        config: &SearchConfig,
+        moves: &[u8],
+        selection_policy: S,
+    ) -> Self {
+        Self::with_root_state(config, NodeState::after_moves(moves), selection_policy)
     }
 }
-impl TreePlayer2 {
-    pub fn new(move_by_player1: u8) -> Self {
-        let nodes = Vec::with_capacity(Self::INITIAL_N_NODES);
-        let edges = Vec::with_capacity(Self::INITIAL_N_NODES * Self::GUESSTIMATE_AVG_CHILDREN);
 
-        let lookup_without_root = HashMap::with_capacity(Self::INITIAL_N_NODES);
+impl<S: SelectionPolicy, R: RolloutPolicy> Tree<S, R> {
+    fn with_root_state(config: &SearchConfig, root_state: NodeState, selection_policy: S) -> Self
+    where
+        R: Default,
+    {
+        let endgame_solver = EndgameSolver::new(config.endgame_empty_cells);
+        let proof_number_search = ProofNumberSearch::new(config.proof_number_budget);
+
+        // the reserved capacity counts towards the memory budget, the lookup rounds up to at
+        // most twice the entries
+        let bytes_per_node = size_of::<Node>()
+            + config.guesstimate_avg_children * size_of::<Edge>()
+            + 2 * TranspositionTable::BYTES_PER_ENTRY;
+        let bytes_for_nodes = config
+            .memory_budget
+            .saturating_sub(endgame_solver.memory_used() + proof_number_search.memory_used());
+        let initial_n_nodes = config
+            .initial_n_nodes
+            .min(bytes_for_nodes / bytes_per_node)
+            .max(1);
+
+        let nodes = Vec::with_capacity(initial_n_nodes);
+        let edges = Vec::with_capacity(initial_n_nodes * config.guesstimate_avg_children);
+
+        let lookup_without_root = TranspositionTable::with_capacity(initial_n_nodes);
 
         let mut this = Self {
             root: 0,
@@ -120,17 +224,64 @@ impl TreePlayer2 {
             edges,
             lookup_without_root,
             edge_selection_buf: [0; consts::N_CELLS_NESTED as usize],
+            selection_policy,
+            rollout_policy: R::default(),
+            memory_budget: config.memory_budget,
+            degraded: false,
+            last_search: LastSearch::default(),
+            deadline: None,
+            endgame_solver,
+            proof_number_search,
+            root_proof_attempted: false,
+            final_selection: config.final_selection,
+            rng: SearchRng::from_seed(config.seed),
+            playouts_per_leaf: config.playouts_per_leaf,
+            reuse_simulation_buffer: config.reuse_simulation_buffer,
+            simulation_buffer: SimulationState::empty(),
+            lane_playouts: config.lane_playouts,
+            lane_rngs: lane_rngs(config.seed),
         };
 
-        this.insert_root_node_player2(move_by_player1);
+        this.insert_root_node(root_state);
 
         this
     }
-    fn insert_root_node_player2(&mut self, move_by_player1: u8) -> NodeIdx {
+    /// replaces the random stream of the tree, e.g. to give parallel searches independent ones
+    fn reseed(&mut self, seed: u64) {
+        self.rng = SearchRng::from_seed(seed);
+        self.lane_rngs = lane_rngs(seed);
+    }
+    /// replaces the policy used to play out newly expanded nodes
+    pub fn with_rollout_policy<R2: RolloutPolicy>(self, rollout_policy: R2) -> Tree<S, R2> {
+        Tree {
+            root: self.root,
+            nodes: self.nodes,
+            edges: self.edges,
+            lookup_without_root: self.lookup_without_root,
+            edge_selection_buf: self.edge_selection_buf,
+            selection_policy: self.selection_policy,
+            rollout_policy,
+            memory_budget: self.memory_budget,
+            degraded: self.degraded,
+            last_search: self.last_search,
+            deadline: self.deadline,
+            endgame_solver: self.endgame_solver,
+            proof_number_search: self.proof_number_search,
+            root_proof_attempted: self.root_proof_attempted,
+            final_selection: self.final_selection,
+            rng: self.rng,
+            playouts_per_leaf: self.playouts_per_leaf,
+            reuse_simulation_buffer: self.reuse_simulation_buffer,
+            simulation_buffer: self.simulation_buffer,
+            lane_playouts: self.lane_playouts,
+            lane_rngs: self.lane_rngs,
+        }
+    }
+
+    fn insert_root_node(&mut self, node_state: NodeState) -> NodeIdx {
         debug_assert_eq!(self.nodes.len(), 0);
         debug_assert_eq!(self.edges.len(), 0);
         let idx = 0;
-        let node_state = NodeState::empty().apply_move(move_by_player1).0;
 
         let available_children = node_state.available_in_board_or_fallback();
         let child_count = bitmagic::count_ones_u128(available_children.get()) as u8;
@@ -143,21 +294,21 @@ impl TreePlayer2 {
         self.nodes.push(Node {
             game_state: node_state,
             visits: 0,
-            score: 0,
+            value: 0.0,
+            value_squared: 0.0,
+            playout_visits: 0,
+            playout_score: 0.0,
+            playout_score_squared: 0.0,
             child_count,
             first_edge,
+            proof: None,
         });
+        self.nodes[idx as usize].proof = self.initial_proof(&node_state, child_count);
 
         idx
-        // no need to add this to the lookup, empty state can not be reused as its impossible
-        // to clear occupied cells
+        // no need to add this to the lookup, the root state can not be reached again as its
+        // impossible to clear occupied cells
     }
-}
-
-impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
-    const INITIAL_N_NODES: usize = 5_000_000;
-    /// pulled straight out of where the sun dont shine
-    const GUESSTIMATE_AVG_CHILDREN: usize = 30;
 
     /// changes the root by choosing the child with the corresponding move
     /// # Returns
@@ -176,107 +327,221 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
             .child_node
             .expect("explored edges must have their child_node set")
             .get();
+        self.root_proof_attempted = false;
         self.root
     }
 
-    pub fn apply_maybe_explored_move(&mut self, move_: u8) -> NodeIdx {
-        let root_node = self.nodes[self.root as usize];
-        assert_ne!(root_node.child_count, 0);
-
-        let edges = &self.edges[root_node.first_edge as usize
-            ..(root_node.first_edge as usize + root_node.child_count as usize)];
-        let target_edge_opt = edges.iter().find(|edge| edge.move_ == move_);
-        if let Some(target_edge) = target_edge_opt {
-            self.root = target_edge
-                .child_node
-                .expect("explored edges must have their child_node set")
-                .get();
-        } else {
-            let available_moves: u128 = root_node.game_state.available_in_board_or_fallback().get();
-            // remove all moves including and above the selected, the index of the move is the
-            // amount of less significant 1
-            let available_moves = available_moves << (128 - move_);
-            let move_edge_idx = bitmagic::count_ones_u128(available_moves);
-            debug_assert_eq!(edges[move_edge_idx as usize].child_node, None);
-            debug_assert_eq!(edges[move_edge_idx as usize].move_, 0);
+    /// Drops every node and edge that can no longer be reached from the current root
+    /// (e.g. the sibling branches abandoned by [`Self::apply_explored_move`]).
+    ///
+    /// Meant to be run between turns, the preallocated capacity is kept.
+    /// Surviving nodes and edges keep their relative order which allows compacting in place.
+    /// Index 0 is kept as a placeholder so that no edge can ever point to it.
+    pub fn compact(&mut self) {
+        // nothing was abandoned yet
+        if self.root == 0 {
+            return;
+        }
+        const UNREACHABLE: NodeIdx = NodeIdx::MAX;
+        const REACHABLE: NodeIdx = 0;
+
+        let mut new_idx = vec![UNREACHABLE; self.nodes.len()];
+        let mut to_visit = vec![self.root];
+        new_idx[self.root as usize] = REACHABLE;
+        while let Some(node_idx) = to_visit.pop() {
+            let node = &self.nodes[node_idx as usize];
+            let edges = &self.edges
+                [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+            for child_node in edges.iter().filter_map(|edge| edge.child_node) {
+                if new_idx[child_node.get() as usize] == UNREACHABLE {
+                    new_idx[child_node.get() as usize] = REACHABLE;
+                    to_visit.push(child_node.get());
+                }
+            }
+        }
 
-            let child_node = self.get_or_insert_node(root_node.game_state, move_);
+        // NOTE: the node at index 0 is never reachable (cells can not be un-set), so every
+        // surviving node moves to an index <= its old one
+        let mut n_reachable: NodeIdx = 1;
+        for idx in new_idx.iter_mut().filter(|idx| **idx == REACHABLE) {
+            *idx = n_reachable;
+            n_reachable += 1;
+        }
 
-            let edge_for_move = &mut self.edges[(root_node.first_edge + move_edge_idx) as usize];
-            edge_for_move.move_ = move_;
-            edge_for_move.child_node = NonZero::new(child_node);
-            self.root = child_node;
+        // edges are appended in the order nodes are inserted, so moving them front to back
+        // never overwrites a block that still has to be moved
+        let mut edge_cursor = 0;
+        for (old_idx, _) in new_idx
+            .iter()
+            .enumerate()
+            .filter(|(_, new_idx)| **new_idx != UNREACHABLE)
+        {
+            let node = &mut self.nodes[old_idx];
+            let old_edges =
+                node.first_edge as usize..(node.first_edge as usize + node.child_count as usize);
+            node.first_edge = edge_cursor as NodeIdx;
+            self.edges.copy_within(old_edges, edge_cursor);
+
+            for edge in &mut self.edges[edge_cursor..(edge_cursor + node.child_count as usize)] {
+                edge.child_node = edge.child_node.map(|child_node| {
+                    NonZero::new(new_idx[child_node.get() as usize])
+                        .expect("children of reachable nodes are never moved to 0")
+                });
+            }
+            edge_cursor += node.child_count as usize;
         }
+        self.edges.truncate(edge_cursor);
+
+        self.nodes[0] = Node::default();
+        for (old_idx, new_idx) in new_idx
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, new_idx)| *new_idx != UNREACHABLE)
         {
-            let merbug_root_node = self.nodes[self.root as usize];
-            let available_mask = merbug_root_node
-                .game_state
-                .available_in_board_or_fallback()
-                .get();
-            let forced = merbug_root_node.game_state.forced_board();
-            let forced_idx_min = forced * 9;
-            let forced_idx_max_incl = forced_idx_min + 8;
-            eprintln!(
-                "MERBUG forced/available: {forced} e [{forced_idx_min}, {forced_idx_max_incl}]\n{available_mask:081b}"
-            );
-            let wins_p1 = [0, 1, 2, 3, 4, 5, 6, 7, 8].map(|board_idx| {
-                let board = merbug_root_node
-                    .game_state
-                    .get_player_board(Player::Player1, board_idx);
-                (format!("{:09b}", board.get()), board.has_won())
-            });
-            let wins_p2 = [0, 1, 2, 3, 4, 5, 6, 7, 8].map(|board_idx| {
-                let board = merbug_root_node
-                    .game_state
-                    .get_player_board(Player::Player2, board_idx);
-                (format!("{:09b}", board.get()), board.has_won())
-            });
-            eprintln!(
-                "MERBUG wins p1: {:09b}\n{wins_p1:?}",
-                merbug_root_node
-                    .game_state
-                    .super_board_for_player(Player::Player1)
-            );
-            eprintln!(
-                "MERBUG wins p2: {:09b}\n{wins_p2:?}",
-                merbug_root_node
-                    .game_state
-                    .super_board_for_player(Player::Player2)
-            );
+            self.nodes[new_idx as usize] = self.nodes[old_idx];
         }
+        self.nodes.truncate(n_reachable as usize);
+        self.root = new_idx[self.root as usize];
+
+        self.lookup_without_root.clear();
+        let nodes = &self.nodes;
+        for (node_idx, node) in nodes.iter().enumerate().skip(1) {
+            if node_idx as NodeIdx != self.root {
+                self.lookup_without_root.insert_new(
+                    &node.game_state,
+                    node_idx as NodeIdx,
+                    |node_idx| nodes[node_idx as usize].game_state,
+                );
+            }
+        }
+        // the search will report again if the freed space does not suffice
+        self.degraded = false;
+        // most cached endgames belonged to the abandoned branches
+        self.endgame_solver.clear();
+    }
+
+    pub fn apply_maybe_explored_move(&mut self, move_: u8) -> NodeIdx {
+        assert_ne!(self.nodes[self.root as usize].child_count, 0);
+        self.root = self.explore_edge(self.root, move_);
+        self.root_proof_attempted = false;
         self.root
     }
 
-    fn get_or_insert_node(&mut self, previous_state: NodeState, move_: u8) -> NodeIdx {
-        let player_that_maybe_wins = previous_state.active_player();
-        let (new_node_state, has_won) = previous_state.apply_move(move_);
+    /// same as [`Self::try_explore_edge`] but the child is inserted no matter the budget
+    fn explore_edge(&mut self, parent_node_idx: NodeIdx, move_: u8) -> NodeIdx {
+        self.try_explore_edge(parent_node_idx, move_, usize::MAX)
+            .expect("the tree ran out of node indices")
+    }
+
+    /// the child of the move, inserted and linked to its edge if the edge was not explored yet,
+    /// None if inserting it would exceed `memory_budget` (see [`Self::try_get_or_insert_node`])
+    fn try_explore_edge(
+        &mut self,
+        parent_node_idx: NodeIdx,
+        move_: u8,
+        memory_budget: usize,
+    ) -> Option<NodeIdx> {
+        let parent_node = self.nodes[parent_node_idx as usize];
+
+        let edges = &self.edges[parent_node.first_edge as usize
+            ..(parent_node.first_edge as usize + parent_node.child_count as usize)];
+        // NOTE: unexplored edges have a default move of 0
+        let target_edge_opt = edges
+            .iter()
+            .find(|edge| edge.child_node.is_some() && edge.move_ == move_);
+        if let Some(target_edge) = target_edge_opt {
+            return Some(
+                target_edge
+                    .child_node
+                    .expect("explored edges must have their child_node set")
+                    .get(),
+            );
+        }
 
-        match self.lookup_without_root.entry(new_node_state) {
-            Entry::Occupied(occupied_entry) => *occupied_entry.get(),
-            Entry::Vacant(vacant_entry) => {
-                let idx = self.nodes.len() as u32;
+        let available_moves: u128 = parent_node
+            .game_state
+            .available_in_board_or_fallback()
+            .get();
+        // remove all moves including and above the selected, the index of the move is the
+        // amount of less significant 1
+        let available_moves = available_moves & ((1 << move_) - 1);
+        let move_edge_idx = bitmagic::count_ones_u128(available_moves);
+        debug_assert_eq!(edges[move_edge_idx as usize].child_node, None);
+        debug_assert_eq!(edges[move_edge_idx as usize].move_, 0);
+
+        let child_node =
+            self.try_get_or_insert_node(parent_node.game_state, move_, memory_budget)?;
+
+        let edge_for_move = &mut self.edges[(parent_node.first_edge + move_edge_idx) as usize];
+        edge_for_move.move_ = move_;
+        edge_for_move.child_node = NonZero::new(child_node);
+        Some(child_node)
+    }
 
-                let (score, child_count) = if has_won {
-                    // games where someone won have no children
-                    (
-                        if player_that_maybe_wins as u8 == SCORE_IN_FAVOR_OF {
-                            1
-                        } else {
-                            -1
-                        },
-                        0,
-                    )
+    /// approximate number of bytes occupied by the tree: the capacity of the nodes, edges and
+    /// the lookup as well as the memory of the solvers
+    pub fn memory_used(&self) -> usize {
+        self.memory_used_after_inserting(0, 0)
+    }
+
+    /// [`Self::memory_used`] once `n_nodes` with `n_edges` in total are inserted, the arrays
+    /// double their capacity when they are full
+    fn memory_used_after_inserting(&self, n_nodes: usize, n_edges: usize) -> usize {
+        fn bytes_after_pushing<T>(vec: &Vec<T>, additional: usize) -> usize {
+            let capacity = if vec.len() + additional > vec.capacity() {
+                (2 * vec.capacity()).max(vec.len() + additional)
+            } else {
+                vec.capacity()
+            };
+            capacity * size_of::<T>()
+        }
+        bytes_after_pushing(&self.nodes, n_nodes)
+            + bytes_after_pushing(&self.edges, n_edges)
+            + self
+                .lookup_without_root
+                .memory_used_after_inserting(n_nodes)
+            + self.endgame_solver.memory_used()
+            + self.proof_number_search.memory_used()
+    }
+
+    /// true if the memory budget was exhausted, the search keeps running playouts from the
+    /// leaves but the tree does not grow anymore until [`Self::compact`] frees enough space
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// # Returns
+    /// - the existing node for the resulting state if there is one
+    /// - otherwise the newly inserted node
+    /// - None if inserting it would exceed `memory_budget`
+    fn try_get_or_insert_node(
+        &mut self,
+        previous_state: NodeState,
+        move_: u8,
+        memory_budget: usize,
+    ) -> Option<NodeIdx> {
+        let (new_node_state, has_won) = previous_state.apply_move(move_);
+        let n_nodes = self.nodes.len();
+
+        let state_of = |node_idx: NodeIdx| self.nodes[node_idx as usize].game_state;
+        match self.lookup_without_root.probe(&new_node_state, state_of) {
+            Probe::Found(node_idx) => Some(node_idx),
+            Probe::Vacant(slot_idx) => {
+                // games where someone won or that are settled have no children
+                let child_count = if has_won || new_node_state.is_settled() {
+                    0
                 } else {
                     let available_children = new_node_state.available_in_board_or_fallback();
-                    let child_count = bitmagic::count_ones_u128(available_children.get()) as u8;
-                    let score = if child_count == 0 {
-                        new_node_state.decide_draw(Player::from_is_player2(SCORE_IN_FAVOR_OF != 0))
-                    } else {
-                        0
-                    };
-                    (score, child_count)
+                    bitmagic::count_ones_u128(available_children.get()) as u8
                 };
 
+                let memory_needed = self.memory_used_after_inserting(1, child_count as usize);
+                if memory_needed > memory_budget || n_nodes >= NodeIdx::MAX as usize {
+                    return None;
+                }
+                let idx = n_nodes as NodeIdx;
+
                 // yes this is unnecessary for terminal nodes but it is preferable to not branch
                 // as it doesn't cost much and the vast majority of nodes are non-terminal
                 let first_edge = self.edges.len() as u32;
@@ -284,18 +549,123 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
                 self.edges
                     .extend(iter::repeat_n(Edge::default(), child_count as usize));
 
+                let proof = self.initial_proof(&new_node_state, child_count);
                 self.nodes.push(Node {
                     game_state: new_node_state,
                     visits: 0,
-                    score,
+                    value: 0.0,
+                    value_squared: 0.0,
+                    playout_visits: 0,
+                    playout_score: 0.0,
+                    playout_score_squared: 0.0,
                     child_count,
                     first_edge,
+                    proof,
                 });
 
-                vacant_entry.insert(idx);
-                idx
+                let nodes = &self.nodes;
+                self.lookup_without_root.insert_vacant(
+                    slot_idx,
+                    &new_node_state,
+                    idx,
+                    |node_idx| nodes[node_idx as usize].game_state,
+                );
+                Some(idx)
+            }
+        }
+    }
+
+    /// the player to move at the root, whose perspective [`Self::root_value`] and
+    /// [`Self::best_explored_move`] take
+    pub fn player_to_move(&self) -> Player {
+        self.nodes[self.root as usize].game_state.active_player()
+    }
+
+    /// the mean result of the search at the root in favor of `player`, in -1..=1
+    pub fn root_value(&self, player: Player) -> f32 {
+        let value = self.nodes[self.root as usize].value;
+        if player == self.player_to_move() {
+            value
+        } else {
+            -value
+        }
+    }
+
+    /// the proof of a new node: terminal nodes are decided, positions close to the end of the
+    /// game are solved by the [`EndgameSolver`], everything else is left to the search
+    fn initial_proof(&mut self, game_state: &NodeState, child_count: u8) -> Option<Proof> {
+        if child_count == 0 {
+            return Some(Proof::from_score(game_state.terminal_score()));
+        }
+        self.endgame_solver
+            .try_solve(game_state, self.deadline)
+            .map(Proof::from_score)
+    }
+
+    /// the proof-number search rarely proves a position with more empty cells before running
+    /// out of nodes, which takes ~15ms
+    const MAX_EMPTY_CELLS_TO_PROVE: u32 = 40;
+    /// bigger roots are only tried with at least this much time, e.g. on the first turn
+    const MIN_TIME_TO_PROVE_ANY_ROOT: Duration = Duration::from_millis(200);
+
+    /// the deadline of the proof-number search in a search until `instant`, which takes at most
+    /// half of the time, None if the time is up already
+    fn root_proof_deadline(instant: Instant) -> Option<Instant> {
+        let now = Instant::now();
+        (now < instant).then(|| now + (instant - now) / 2)
+    }
+
+    /// Runs the [`ProofNumberSearch`] on the root unless that was done for this root already,
+    /// the root is proven or it is too big to be proven in time.
+    /// # Returns
+    /// the result if the search ran, see [`Self::apply_root_proof`]
+    fn try_prove_root(&mut self, deadline: Option<Instant>) -> Option<ProofNumberResult> {
+        let root_node = self.nodes[self.root as usize];
+        if self.root_proof_attempted || root_node.proof.is_some() {
+            return None;
+        }
+        let small_enough = root_node.game_state.n_empty_cells() <= Self::MAX_EMPTY_CELLS_TO_PROVE;
+        let enough_time = deadline.is_none_or(|deadline| {
+            deadline.saturating_duration_since(Instant::now()) >= Self::MIN_TIME_TO_PROVE_ANY_ROOT
+        });
+        if !(small_enough || enough_time) {
+            return None;
+        }
+
+        let result = self
+            .proof_number_search
+            .prove(&root_node.game_state, deadline);
+        self.apply_root_proof(result);
+        Some(result)
+    }
+
+    /// Takes over the result of the [`ProofNumberSearch`] on the root, also from another tree
+    /// with the same root. A proven win links and proves the winning child, so that
+    /// [`Self::best_explored_move`] plays it, a proven loss only proves the root.
+    ///
+    /// A degraded tree does not grow for the win, which is dropped unless its child is linked
+    /// already.
+    fn apply_root_proof(&mut self, result: ProofNumberResult) {
+        self.root_proof_attempted = true;
+        match result {
+            ProofNumberResult::Win { move_ } => {
+                let memory_budget = if self.degraded { 0 } else { self.memory_budget };
+                let Some(child_node_idx) = self.try_explore_edge(self.root, move_, memory_budget)
+                else {
+                    self.degraded = true;
+                    return;
+                };
+                // the opponent moves after the winning move, and loses
+                self.nodes[child_node_idx as usize].proof = Some(Proof::Loss);
+                self.update_value(child_node_idx);
+                self.update_proof(self.root);
+            }
+            ProofNumberResult::Loss => {
+                self.nodes[self.root as usize].proof = Some(Proof::Loss);
             }
+            ProofNumberResult::Unknown => return,
         }
+        self.update_value(self.root);
     }
 
     /// should only be called if root is not at a terminal state
@@ -303,46 +673,306 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
         self.search_n(50_000);
     }
     pub fn search_n(&mut self, n: usize) {
+        self.last_search = LastSearch::start();
         for _i in 0..n {
-            let _score_from_leaf = self.expand(self.root);
+            self.iterate();
         }
+        self.last_search.finish();
     }
     pub fn search_flag(&mut self, keep_going: AtomicBool) {
+        self.last_search = LastSearch::start();
         // TOOD: i think this ordering is fine but don't know for sure
         while keep_going.load(std::sync::atomic::Ordering::Acquire) {
-            let _score_from_leaf = self.expand(self.root);
+            self.iterate();
         }
+        self.last_search.finish();
     }
+    /// NOTE: also tries to prove the root with the proof-number search (once per root), which
+    /// takes at most half of the time, see [`Self::try_prove_root`]
     pub fn search_until(&mut self, instant: Instant) {
+        self.last_search = LastSearch::start();
+        self.deadline = Some(instant);
+        if let Some(proof_deadline) = Self::root_proof_deadline(instant) {
+            self.try_prove_root(Some(proof_deadline));
+        }
         while instant > Instant::now() {
-            let _score_from_leaf = self.expand(self.root);
+            self.iterate();
+        }
+        self.deadline = None;
+        self.last_search.finish();
+    }
+    /// Like [`Self::search_until`], but the [`TimeManager`] decides when to stop based on how
+    /// settled the choice of the move is.
+    pub fn search_timed(&mut self, time_manager: &TimeManager) {
+        self.last_search = LastSearch::start();
+        self.deadline = Some(time_manager.hard_deadline());
+        if let Some(proof_deadline) = Self::root_proof_deadline(time_manager.soft_deadline()) {
+            self.try_prove_root(Some(proof_deadline));
+        }
+        // the proof-number search does not count towards the iteration rate
+        let search_start = Instant::now();
+        let mut iterations: u64 = 0;
+        loop {
+            self.iterate();
+            iterations += 1;
+            let now = Instant::now();
+            if now >= time_manager.hard_deadline() {
+                break;
+            }
+            if iterations == 1 || iterations.is_multiple_of(Self::ITERATIONS_PER_PROGRESS_CHECK) {
+                let iterations_per_second =
+                    iterations as f32 / now.duration_since(search_start).as_secs_f32();
+                if time_manager.should_stop(now, &self.search_progress(iterations_per_second)) {
+                    break;
+                }
+            }
+        }
+        self.deadline = None;
+        self.last_search.finish();
+    }
+    /// Keeps searching from the current root until `poll` yields something.
+    /// Meant for the opponent's turn (pondering), the search takes the perspective of the
+    /// opponent at the root like it would for us.
+    /// # Returns
+    /// the first value yielded by `poll`, e.g. the opponent's move
+    pub fn ponder<T>(&mut self, mut poll: impl FnMut() -> Option<T>) -> T {
+        self.last_search = LastSearch::start();
+        loop {
+            if let Some(result) = poll() {
+                self.last_search.finish();
+                return result;
+            }
+            self.iterate();
         }
     }
 
-    pub fn best_explored_move(&self) -> u8 {
+    fn iterate(&mut self) {
+        let depth = self.expand(self.root);
+        self.last_search.record(depth);
+    }
+
+    /// how many iterations [`Self::search_timed`] runs between two looks at the root
+    const ITERATIONS_PER_PROGRESS_CHECK: u64 = 64;
+
+    fn search_progress(&self, iterations_per_second: f32) -> SearchProgress {
+        let root_node = &self.nodes[self.root as usize];
+        let (mut most_visits, mut second_most_visits) = (0, 0);
+        for (_, visits, _) in self.explored_root_children() {
+            if visits > most_visits {
+                second_most_visits = most_visits;
+                most_visits = visits;
+            } else if visits > second_most_visits {
+                second_most_visits = visits;
+            }
+        }
+        SearchProgress {
+            n_moves: root_node.child_count,
+            solved: root_node
+                .proof
+                .is_some_and(|proof| self.explored_root_child_achieves(proof)),
+            most_visits,
+            second_most_visits,
+            iterations_per_second,
+            disputed: self.is_final_selection_disputed(),
+        }
+    }
+
+    /// whether the move achieving the (proven) value of the root is known
+    fn explored_root_child_achieves(&self, root_proof: Proof) -> bool {
+        self.explored_root_children()
+            .any(|(_, _, child)| child.proof.map(Proof::flipped) == Some(root_proof))
+    }
+
+    /// (move, edge visits, child) for every explored edge of the root
+    fn explored_root_children(&self) -> impl Iterator<Item = (u8, u32, &Node)> {
         let root_node = &self.nodes[self.root as usize];
         self.edges[root_node.first_edge as usize
             ..root_node.first_edge as usize + root_node.child_count as usize]
             .iter()
-            .filter_map(|edge| edge.child_node.map(|child_node| (edge, child_node)))
-            .max_by_key(|(_, child_node)| self.nodes[child_node.get() as usize].visits)
+            .filter_map(|edge| {
+                edge.child_node.map(|child_node| {
+                    (
+                        edge.move_,
+                        edge.visits,
+                        &self.nodes[child_node.get() as usize],
+                    )
+                })
+            })
+    }
+
+    /// the move chosen by [`SearchConfig::final_selection`], but always a proven win and never a
+    /// proven loss if avoidable
+    pub fn best_explored_move(&self) -> u8 {
+        self.best_explored_move_by(self.final_selection)
+    }
+
+    fn best_explored_move_by(&self, final_selection: FinalSelection) -> u8 {
+        let root_node = &self.nodes[self.root as usize];
+        let parent_visits = root_node.visits;
+        let parent_visits_ln = (parent_visits as UCBScore).ln();
+        self.explored_root_children()
+            .map(|(move_, visits, child)| {
+                let proof_for_player_to_move = child.proof.map_or(0, |proof| -proof.score());
+                let rating = final_selection.rate(&ChildStats {
+                    parent_visits,
+                    parent_visits_ln,
+                    // the child's value is in favor of the opponent
+                    mean_score: -child.value,
+                    mean_squared_score: child.value_squared,
+                    visits,
+                    move_,
+                    n_moves: root_node.child_count,
+                });
+                (move_, proof_for_player_to_move, rating)
+            })
+            .max_by(|(_, proof_a, rating_a), (_, proof_b, rating_b)| {
+                proof_a.cmp(proof_b).then(rating_a.total_cmp(rating_b))
+            })
             .expect("at least one child must have been explored")
             .0
-            .move_
     }
 
-    fn expand(&mut self, parent_node_idx: NodeIdx) -> MonteCarloScore {
-        let parent_node = &mut self.nodes[parent_node_idx as usize];
-        parent_node.visits += 1;
+    /// [`FinalSelection::RobustMax`] waits for the most visited move to have the best score
+    fn is_final_selection_disputed(&self) -> bool {
+        self.final_selection == FinalSelection::RobustMax
+            && self.best_explored_move_by(FinalSelection::Robust)
+                != self.best_explored_move_by(FinalSelection::Max)
+    }
 
-        // terminal leaf node
-        if parent_node.child_count == 0 {
-            // a terminal node always results in the same result
-            // - visits * 1 / 0 / visits * -1
-            // so this division is guaranteed to be accurate and avoids over accumulation
-            let score_for_terminal = parent_node.score / parent_node.visits as i32;
-            parent_node.score += score_for_terminal;
-            return score_for_terminal;
+    /// Solves the node if the results of its children allow it (MCTS-Solver)
+    /// - the player to move wins if any child is a win for them
+    /// - otherwise, once all children are solved, the best of them for the player to move
+    fn update_proof(&mut self, node_idx: NodeIdx) -> Option<Proof> {
+        let node = &self.nodes[node_idx as usize];
+        let edges = &self.edges
+            [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+
+        let mut all_solved = true;
+        let mut best_score = MonteCarloScore::MIN;
+        for edge in edges {
+            match edge
+                .child_node
+                .and_then(|child_node| self.nodes[child_node.get() as usize].proof)
+            {
+                // the child's proof is in favor of the opponent
+                Some(proof) => best_score = best_score.max(-proof.score()),
+                None => all_solved = false,
+            }
+        }
+
+        let proof =
+            (best_score == Proof::Win.score() || all_solved).then(|| Proof::from_score(best_score));
+        // nodes proven by the endgame solver stay proven while their children are explored
+        let node = &mut self.nodes[node_idx as usize];
+        node.proof = proof.or(node.proof);
+        node.proof
+    }
+
+    /// Recomputes the statistics of the node from its own playouts and its children
+    /// (Monte Carlo graph search), proven nodes always take the value of their proof.
+    /// The values of the children are in favor of the opponent, so they are negated.
+    fn update_value(&mut self, node_idx: NodeIdx) {
+        let node = &self.nodes[node_idx as usize];
+        let edges = &self.edges
+            [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+
+        let mut visits = node.playout_visits;
+        let mut score = node.playout_score;
+        let mut score_squared = node.playout_score_squared;
+        for edge in edges {
+            if let Some(child_node) = edge.child_node {
+                let child = &self.nodes[child_node.get() as usize];
+                visits += edge.visits;
+                score -= edge.visits as f32 * child.value;
+                score_squared += edge.visits as f32 * child.value_squared;
+            }
+        }
+
+        let node = &mut self.nodes[node_idx as usize];
+        node.visits = visits;
+        if let Some(proof) = node.proof {
+            node.value = proof.score() as f32;
+            node.value_squared = node.value * node.value;
+        } else if visits != 0 {
+            node.value = score / visits as f32;
+            node.value_squared = score_squared / visits as f32;
+        }
+    }
+
+    /// the mean result of [`SearchConfig::playouts_per_leaf`] playouts, in favor of the player to
+    /// move in the state, which must not be terminal
+    fn playout(&mut self, game_state: &NodeState) -> f32 {
+        let simulation = game_state.into_simulation();
+        if self.lane_playouts && R::IS_UNIFORM {
+            let n_batches = self.playouts_per_leaf.get().div_ceil(LANES as u32);
+            let score_sum: MonteCarloScore = (0..n_batches)
+                .map(|_| {
+                    LaneSimulation::new(&simulation)
+                        .simulate(&mut self.lane_rngs)
+                        .iter()
+                        .sum::<MonteCarloScore>()
+                })
+                .sum();
+            return score_sum as f32 / (n_batches * LANES as u32) as f32;
+        }
+        let score_sum = if self.reuse_simulation_buffer {
+            simulation.simulate_n_in(
+                &mut self.simulation_buffer,
+                self.playouts_per_leaf.get(),
+                &self.rollout_policy,
+                &mut self.rng,
+            )
+        } else {
+            simulation.simulate_n(
+                self.playouts_per_leaf.get(),
+                &self.rollout_policy,
+                &mut self.rng,
+            )
+        };
+        score_sum as f32 / self.playouts_per_leaf.get() as f32
+    }
+
+    /// Out of memory: plays out from the child of the move but only the parent keeps the
+    /// result, the edge stays unvisited.
+    fn playout_without_expanding(&mut self, parent_node_idx: NodeIdx, move_: u8) {
+        // reported through `is_degraded` and the search stats
+        self.degraded = true;
+
+        let parent_node = &self.nodes[parent_node_idx as usize];
+        let (child_state, has_won) = parent_node.game_state.apply_move(move_);
+        let child_score = if has_won || child_state.is_settled() {
+            child_state.terminal_score() as f32
+        } else {
+            self.playout(&child_state)
+        };
+        self.nodes[parent_node_idx as usize].add_playout(-child_score);
+        self.update_value(parent_node_idx);
+    }
+
+    /// Runs one iteration below the node: selects along the edge statistics, expands one edge
+    /// and plays out from its child, then updates the values on the way back up.
+    ///
+    /// Children reachable from several parents are not descended into if the edge has fewer
+    /// visits than the child, the edge merely catches up to the child's value instead.
+    ///
+    /// NOTE: the scores of a node are in favor of the player to move at it, so a parent negates
+    /// the scores of its children
+    /// # Returns
+    /// the depth of the node the iteration ended at, relative to `parent_node_idx`
+    fn expand(&mut self, parent_node_idx: NodeIdx) -> u32 {
+        let parent_node = &self.nodes[parent_node_idx as usize];
+
+        // solved subtree (includes terminal leaf nodes) always results in the same result,
+        // except that a root proven without its children (endgame solver, proof-number search)
+        // still needs an explored child which achieves the proof to choose a move from
+        if let Some(proof) = parent_node.proof
+            && (parent_node_idx != self.root
+                || parent_node.child_count == 0
+                || self.explored_root_child_achieves(proof))
+        {
+            self.nodes[parent_node_idx as usize].add_playout(proof.score() as f32);
+            self.update_value(parent_node_idx);
+            return 0;
         }
 
         let edge_offset = parent_node.first_edge as usize;
@@ -353,17 +983,19 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
         // all unvisited edges have an infite UCB so they are all the max and we have to choose one
         // randomly
         let mut unvisited_edge_counter = 0;
-        for (relative_edge_idx, _) in edges
-            .iter()
-            .enumerate()
-            .filter(|(_, edge)| edge.child_node.is_none())
-        {
-            self.edge_selection_buf[unvisited_edge_counter] = relative_edge_idx as NodeIdx;
-            unvisited_edge_counter += 1;
+        let mut parent_visits = 0;
+        for (relative_edge_idx, edge) in edges.iter().enumerate() {
+            if edge.visits == 0 {
+                self.edge_selection_buf[unvisited_edge_counter] = relative_edge_idx as NodeIdx;
+                unvisited_edge_counter += 1;
+            }
+            parent_visits += edge.visits;
         }
 
         if unvisited_edge_counter != 0 {
-            let rand_idx = rng::rand_in_move_range_exclusive(unvisited_edge_counter as u8);
+            let rand_idx = self
+                .rng
+                .rand_in_move_range_exclusive(unvisited_edge_counter as u8);
             let rand_unvisited_edge_relative_idx = self.edge_selection_buf[rand_idx as usize];
             let move_ = bitmagic::index_of_nth_setbit(
                 parent_node
@@ -373,7 +1005,12 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
                 rand_unvisited_edge_relative_idx as u8,
             ) as u8;
             let current_state = parent_node.game_state;
-            let child_node_idx = self.get_or_insert_node(current_state, move_);
+            let Some(child_node_idx) =
+                self.try_get_or_insert_node(current_state, move_, self.memory_budget)
+            else {
+                self.playout_without_expanding(parent_node_idx, move_);
+                return 1;
+            };
 
             // NOTE: this should never be zero, a move can not possibly result in the first/empty
             // node as this would mean "un-setting" cells
@@ -383,78 +1020,138 @@ impl<const SCORE_IN_FAVOR_OF: PlayerU8> TreeForPlayer<SCORE_IN_FAVOR_OF> {
             // of inserting a node
             self.edges[edge_absolute_idx].child_node = NonZero::new(child_node_idx);
             self.edges[edge_absolute_idx].move_ = move_;
+            self.edges[edge_absolute_idx].visits = 1;
+
+            let child_node = self.nodes[child_node_idx as usize];
+            let child_proof = child_node.proof;
+            // a transposition which already has a value does not need another playout
+            if child_node.visits == 0 {
+                let score = match child_proof {
+                    Some(proof) => proof.score() as f32,
+                    None => self.playout(&child_node.game_state),
+                };
+                self.nodes[child_node_idx as usize].add_playout(score);
+                self.update_value(child_node_idx);
+            }
 
-            let child_node = &mut self.nodes[child_node_idx as usize];
-            child_node.visits += 1;
-            // NOTE: += intentional because the node might be re-used
-            let score_delta = if child_node.child_count == 0 {
-                // don't double count score from first insertion
-                if child_node.visits == 1 {
-                    0
-                } else {
-                    // a terminal node always results in the same result
-                    // - visits * 1 / 0 / visits * -1
-                    // so this division is guaranteed to be accurate and avoids over accumulation
-                    child_node.score / child_node.visits as i32
-                }
-            } else {
-                child_node.game_state.into_simulation().simulate_random()
-            };
-            child_node.score += score_delta;
-
-            let parent_node = &mut self.nodes[parent_node_idx as usize];
-            // negamax
-            parent_node.score -= score_delta;
-
-            score_delta
+            if child_proof.is_some() {
+                self.update_proof(parent_node_idx);
+            }
+            self.update_value(parent_node_idx);
+            1
         } else {
-            let parent_visits_ln = (parent_node.visits as UCBScore).ln();
-            let (mut max_ucb, mut max_ucb_node) = (f32::MIN, 0);
+            let n_moves = parent_node.child_count;
+            let parent_visits_ln = (parent_visits as UCBScore).ln();
+            let (mut max_ucb, mut max_ucb_edge) = (f32::MIN, None);
 
-            for edge in edges {
+            for (relative_edge_idx, edge) in edges.iter().enumerate() {
                 // safety: if any child node is unvisited the code path above this for loop returns early
                 let child_node_idx = unsafe { edge.child_node.unwrap_unchecked() };
                 let child = &self.nodes[child_node_idx.get() as usize];
-                let child_ucb = upper_confidence_bound(parent_visits_ln, child.score, child.visits);
+                // there is nothing left to learn in solved subtrees
+                if child.proof.is_some() {
+                    continue;
+                }
+                let child_ucb = self.selection_policy.rate(&ChildStats {
+                    parent_visits,
+                    parent_visits_ln,
+                    // the player to move picks the child that is best for them
+                    mean_score: -child.value,
+                    mean_squared_score: child.value_squared,
+                    visits: edge.visits,
+                    move_: edge.move_,
+                    n_moves,
+                });
                 if child_ucb > max_ucb {
                     max_ucb = child_ucb;
-                    max_ucb_node = child_node_idx.get();
+                    max_ucb_edge = Some(edge_offset + relative_edge_idx);
                 }
             }
 
-            // negate because of negamax (a win for a child is a loss for us)
-            let score_delta = -self.expand(max_ucb_node);
-            let parent_node = &mut self.nodes[parent_node_idx as usize];
-            parent_node.score += score_delta;
-            score_delta
+            let Some(max_ucb_edge) = max_ucb_edge else {
+                // all children were solved, e.g. through transpositions from other parents
+                let proof = self
+                    .update_proof(parent_node_idx)
+                    .expect("a node with only solved children is solved");
+                self.nodes[parent_node_idx as usize].add_playout(proof.score() as f32);
+                self.update_value(parent_node_idx);
+                return 0;
+            };
+
+            let edge = self.edges[max_ucb_edge];
+            let child_node_idx = edge
+                .child_node
+                .expect("visited edges have their child_node set")
+                .get();
+            // the child might have been visited more often through other parents, in that case
+            // its value is already backed by enough visits for this edge
+            let depth_below_child = if edge.visits >= self.nodes[child_node_idx as usize].visits {
+                self.expand(child_node_idx)
+            } else {
+                0
+            };
+            self.edges[max_ucb_edge].visits += 1;
+
+            if self.nodes[child_node_idx as usize].proof.is_some() {
+                self.update_proof(parent_node_idx);
+            }
+            self.update_value(parent_node_idx);
+            1 + depth_below_child
         }
     }
 }
 
-type UCBScore = f32;
-
-// TODO: determine better value empirically
-const EXPLORATION_C: UCBScore = core::f32::consts::SQRT_2;
-/// https://en.wikipedia.org/wiki/Monte_Carlo_tree_search
-fn upper_confidence_bound(
-    parent_visits_ln: UCBScore,
-    child_score: MonteCarloScore,
-    child_visits: u32,
-) -> UCBScore {
-    // [-1, 1]
-    let exploitation = (child_score as UCBScore) / child_visits.max(1) as UCBScore;
-    let exploration = EXPLORATION_C * UCBScore::sqrt(parent_visits_ln / child_visits as UCBScore);
-
-    exploitation + exploration
+/// independent streams for the lanes of a [`LaneSimulation`] of a tree seeded with `seed`
+fn lane_rngs(seed: u64) -> [SearchRng; LANES] {
+    std::array::from_fn(|lane| SearchRng::from_seed(rng::stream_seed(seed, lane as u64)))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{consts, tree::TreePlayer1};
+    use std::{
+        iter,
+        num::NonZeroU32,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        config::SearchConfig,
+        consts,
+        rng::SearchRng,
+        tree::{
+            NodeIdx, NodeState, Proof, Tree,
+            endgame::{self, EndgameSolver},
+            lane_simulation::LANES,
+            proof_number::ProofNumberResult,
+            selection::{
+                FinalSelection, Puct, SECURE_C, SelectionPolicy, Ucb1, Ucb1Tuned, UniformPrior,
+            },
+            simulation::HeuristicRollout,
+            time_manager::TimeManager,
+            transposition::Probe,
+        },
+        types::Player,
+    };
+
+    /// ignores the forced boards, only alternates the players
+    fn state_after(moves: &[u8]) -> NodeState {
+        moves.iter().fold(NodeState::empty(), |state, move_| {
+            state.apply_move(*move_).0
+        })
+    }
+
+    /// player 1 to move, has won boards 0 and 1 and is forced into board 2 where playing 20
+    /// wins board 2 and thereby the game
+    fn player1_wins_with_20() -> NodeState {
+        let state = state_after(&[0, 36, 1, 49, 2, 62, 9, 73, 10, 66, 11, 28, 18, 57, 19, 74]);
+        assert_eq!(state.active_player(), Player::Player1);
+        assert_eq!(state.forced_board(), 2);
+        state
+    }
 
     #[test]
     fn search_works_on_root() {
-        let mut tree = TreePlayer1::new();
+        let mut tree = Tree::default();
         tree.search();
         let chosen_move = tree.best_explored_move();
         assert!((0..consts::N_CELLS_NESTED as u8).contains(&chosen_move));
@@ -462,7 +1159,7 @@ mod test {
 
     #[test]
     fn children_are_explored_first() {
-        let mut tree = TreePlayer1::new();
+        let mut tree = Tree::default();
         let root = &tree.nodes[tree.root as usize];
         assert_eq!(root.child_count, consts::N_CELLS_NESTED as u8);
         assert_eq!(tree.nodes.len(), 1);
@@ -513,7 +1210,7 @@ mod test {
 
     #[test]
     fn expand_adds_node() {
-        let mut tree = TreePlayer1::new();
+        let mut tree = Tree::default();
         assert_eq!(tree.nodes.len(), 1);
         tree.expand(0);
         assert_eq!(tree.nodes.len(), 2);
@@ -523,7 +1220,7 @@ mod test {
 
     #[test]
     fn expanded_nodes_are_plausible() {
-        let mut tree = TreePlayer1::new();
+        let mut tree = Tree::default();
         tree.expand(0);
 
         let root = &tree.nodes[0];
@@ -544,7 +1241,7 @@ mod test {
 
     #[test]
     fn apply_move() {
-        let mut tree = TreePlayer1::new();
+        let mut tree = Tree::default();
         tree.expand(tree.root);
         let move_to_apply = tree.best_explored_move();
         let new_root = tree.apply_explored_move(move_to_apply);
@@ -562,4 +1259,612 @@ mod test {
             .count();
         assert_eq!(defined_children_cnt, 1);
     }
+
+    #[test]
+    fn compact_keeps_only_reachable_nodes() {
+        let mut tree = Tree::default();
+        tree.search_n(5_000);
+        let move_to_apply = tree.best_explored_move();
+        tree.apply_explored_move(move_to_apply);
+
+        let root_visits = tree.nodes[tree.root as usize].visits;
+        let best_move_before = tree.best_explored_move();
+        let n_nodes_before = tree.nodes.len();
+
+        tree.compact();
+
+        assert!(tree.nodes.len() < n_nodes_before);
+        assert_eq!(tree.nodes[tree.root as usize].visits, root_visits);
+        assert_eq!(tree.best_explored_move(), best_move_before);
+        // every node but the placeholder and the root must be reachable through the lookup
+        assert_eq!(tree.lookup_without_root.len(), tree.nodes.len() - 2);
+        for (idx, node) in tree.nodes.iter().enumerate().skip(1) {
+            let found = tree
+                .lookup_without_root
+                .probe(&node.game_state, |idx| tree.nodes[idx as usize].game_state);
+            if idx as NodeIdx == tree.root {
+                assert!(matches!(found, Probe::Vacant(_)));
+            } else {
+                assert_eq!(found, Probe::Found(idx as NodeIdx));
+            }
+        }
+        let n_edges: usize = tree
+            .nodes
+            .iter()
+            .map(|node| node.child_count as usize)
+            .sum();
+        assert_eq!(tree.edges.len(), n_edges);
+        for edge in tree.edges.iter().filter_map(|edge| edge.child_node) {
+            assert!((edge.get() as usize) < tree.nodes.len());
+        }
+
+        // compacting twice must not change anything
+        let n_nodes_after = tree.nodes.len();
+        tree.compact();
+        assert_eq!(tree.nodes.len(), n_nodes_after);
+
+        tree.search_n(1_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, root_visits + 1_000);
+    }
+
+    #[test]
+    fn scores_favor_the_player_to_move() {
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig::default(),
+            player1_wins_with_20(),
+            Ucb1::default(),
+        );
+        tree.search_n(500);
+        assert_eq!(tree.player_to_move(), Player::Player1);
+        assert_eq!(tree.best_explored_move(), 20);
+
+        // player 2 is to move after the winning move, and has lost
+        let (_, _, winning_child) = tree
+            .explored_root_children()
+            .find(|(move_, _, _)| *move_ == 20)
+            .unwrap();
+        assert_eq!(winning_child.value, -1.0);
+        assert!(tree.nodes[tree.root as usize].value > 0.0);
+        assert!(tree.root_value(Player::Player1) > 0.0);
+        assert_eq!(
+            tree.root_value(Player::Player2),
+            -tree.root_value(Player::Player1)
+        );
+    }
+
+    #[test]
+    fn settled_positions_are_terminal() {
+        // random games until one is settled before every board is decided
+        let mut rng = SearchRng::from_seed(25);
+        let (parent_state, move_) = 'games: loop {
+            let mut state = NodeState::empty();
+            loop {
+                let available = state.available_in_board_or_fallback().get();
+                let nth = rng.rand_in_move_range_exclusive(available.count_ones() as u8);
+                let move_ = crate::bitmagic::index_of_nth_setbit(available, nth) as u8;
+                let (child_state, has_won) = state.apply_move(move_);
+                if has_won || child_state.all_boards_decided() {
+                    break;
+                }
+                if child_state.is_settled() {
+                    break 'games (state, move_);
+                }
+                state = child_state;
+            }
+        };
+
+        let mut tree =
+            Tree::<Ucb1>::with_root_state(&SearchConfig::default(), parent_state, Ucb1::default());
+        let child_idx = tree.explore_edge(tree.root, move_);
+        let child = &tree.nodes[child_idx as usize];
+        assert_eq!(child.child_count, 0);
+        let player_to_move = child.game_state.active_player();
+        assert_eq!(
+            child.proof.map(Proof::score),
+            child.game_state.settled_score(player_to_move)
+        );
+    }
+
+    #[test]
+    fn pondering_searches_for_the_player_to_move() {
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig::default(),
+            player1_wins_with_20(),
+            Ucb1::default(),
+        );
+        let mut n_polls = 0;
+        let result = tree.ponder(|| {
+            n_polls += 1;
+            (n_polls > 500).then_some(n_polls)
+        });
+        assert_eq!(result, 501);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 500);
+
+        // the opponent (player 1) is expected to find the win, which is a loss for player 2
+        assert_eq!(tree.best_explored_move(), 20);
+        assert!(tree.root_value(Player::Player2) < 0.0);
+    }
+
+    #[test]
+    fn self_play_shares_one_tree() {
+        let mut tree = Tree::default();
+        for n_moves in 0..10 {
+            let player_to_move = tree.player_to_move();
+            assert_eq!(player_to_move, Player::from_is_player2(n_moves % 2 == 1));
+            tree.search_n(2_000);
+            let best_move = tree.best_explored_move();
+            tree.apply_explored_move(best_move);
+            assert_eq!(tree.player_to_move(), player_to_move.other());
+            // the statistics of the reply searched for the other player are kept
+            assert_ne!(tree.nodes[tree.root as usize].visits, 0);
+        }
+    }
+
+    #[test]
+    fn starts_from_moves() {
+        let tree = Tree::from_moves(&SearchConfig::default(), &[40, 36, 4]);
+        assert_eq!(tree.player_to_move(), Player::Player2);
+        assert_eq!(
+            tree.nodes[tree.root as usize].game_state,
+            state_after(&[40, 36, 4])
+        );
+        // 4 forces player 2 into the center board, which has 7 free cells left
+        assert_eq!(tree.nodes[tree.root as usize].child_count, 7);
+    }
+
+    #[test]
+    #[should_panic = "move 40 is not available"]
+    fn rejects_unavailable_moves() {
+        Tree::from_moves(&SearchConfig::default(), &[40, 40]);
+    }
+
+    #[test]
+    fn solver_proves_immediate_win() {
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig::default(),
+            player1_wins_with_20(),
+            Ucb1::default(),
+        );
+        // all 7 children have to be expanded before the win can be found for sure
+        tree.search_n(7);
+        assert_eq!(tree.nodes[tree.root as usize].proof, Some(Proof::Win));
+        assert_eq!(tree.best_explored_move(), 20);
+
+        // searching a solved root does not grow the tree anymore
+        let n_nodes = tree.nodes.len();
+        tree.search_n(100);
+        assert_eq!(tree.nodes.len(), n_nodes);
+
+        assert_eq!(tree.root_value(Player::Player2), -1.0);
+    }
+
+    #[test]
+    fn endgame_solver_proves_late_positions() {
+        let mut rng = SearchRng::from_seed(14);
+        let state = iter::repeat_with(|| endgame::test::random_endgame(12, &mut rng))
+            .flatten()
+            .find(|state| EndgameSolver::new(12).solve(state) == 1)
+            .unwrap();
+
+        let config = SearchConfig {
+            endgame_empty_cells: 12,
+            ..SearchConfig::default()
+        };
+        let mut tree = Tree::<Ucb1>::with_root_state(&config, state, Ucb1::default());
+        assert_eq!(tree.nodes[tree.root as usize].proof, Some(Proof::Win));
+        tree.search_n(100);
+        let best_move = tree.best_explored_move();
+        let (_, _, best_child) = tree
+            .explored_root_children()
+            .find(|(move_, _, _)| *move_ == best_move)
+            .unwrap();
+        // the opponent loses after the best move
+        assert_eq!(best_child.proof, Some(Proof::Loss));
+
+        let config = SearchConfig {
+            endgame_empty_cells: 0,
+            ..SearchConfig::default()
+        };
+        let tree = Tree::<Ucb1>::with_root_state(&config, state, Ucb1::default());
+        assert_eq!(tree.nodes[tree.root as usize].proof, None);
+    }
+
+    #[test]
+    fn timed_search_stops_once_the_move_is_settled() {
+        let budget = Duration::from_secs(1);
+
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig::default(),
+            player1_wins_with_20(),
+            Ucb1::default(),
+        );
+        let time_manager = TimeManager::new(Instant::now(), budget);
+        tree.search_timed(&time_manager);
+        // proven by the proof-number search before the first iteration
+        assert!(Instant::now() < time_manager.soft_deadline());
+        assert_eq!(tree.nodes[tree.root as usize].visits, 1);
+        assert_eq!(tree.best_explored_move(), 20);
+
+        // player 1 is forced into board 4, which has a single free cell left
+        let only_move = state_after(&[
+            39, 31, 36, 5, 45, 2, 25, 70, 67, 42, 58, 37, 10, 14, 51, 56, 19, 13, 41, 49, 43, 65,
+            22, 44, 75, 27, 4, 40,
+        ]);
+        assert_eq!(only_move.available_in_board_or_fallback().get(), 1 << 38);
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig {
+                proof_number_budget: 0,
+                ..SearchConfig::default()
+            },
+            only_move,
+            Ucb1::default(),
+        );
+        let time_manager = TimeManager::new(Instant::now(), budget);
+        tree.search_timed(&time_manager);
+        assert!(Instant::now() < time_manager.soft_deadline());
+        assert_eq!(tree.nodes[tree.root as usize].visits, 1);
+
+        let mut tree = Tree::default();
+        let time_manager = TimeManager::new(Instant::now(), Duration::from_millis(50));
+        tree.search_timed(&time_manager);
+        assert!(Instant::now() < time_manager.hard_deadline() + Duration::from_millis(20));
+        assert!(tree.nodes[tree.root as usize].visits > 1);
+    }
+
+    #[test]
+    fn final_selections_pick_their_move() {
+        for final_selection in [
+            FinalSelection::Robust,
+            FinalSelection::Max,
+            FinalSelection::RobustMax,
+            FinalSelection::Secure,
+        ] {
+            let mut tree = Tree::new(&SearchConfig {
+                final_selection,
+                ..SearchConfig::default()
+            });
+            tree.search_n(3_000);
+            let rating = |move_: u8| {
+                let stats = tree
+                    .root_move_stats()
+                    .into_iter()
+                    .find(|stats| stats.move_ == move_)
+                    .unwrap();
+                match final_selection {
+                    FinalSelection::Robust | FinalSelection::RobustMax => stats.visits as f32,
+                    FinalSelection::Max => stats.mean_score,
+                    FinalSelection::Secure => {
+                        stats.mean_score - SECURE_C / (stats.visits as f32).sqrt()
+                    }
+                }
+            };
+            let best_rating = rating(tree.best_explored_move());
+            assert!(
+                tree.root_move_stats()
+                    .iter()
+                    .all(|stats| rating(stats.move_) <= best_rating),
+                "{final_selection:?}"
+            );
+        }
+
+        // robust-max keeps searching until the most visited move has the best score
+        let mut tree = Tree::new(&SearchConfig {
+            final_selection: FinalSelection::RobustMax,
+            ..SearchConfig::default()
+        });
+        let time_manager = TimeManager::new(Instant::now(), Duration::from_millis(200));
+        tree.search_timed(&time_manager);
+        assert!(
+            !tree.is_final_selection_disputed() || Instant::now() >= time_manager.hard_deadline()
+        );
+    }
+
+    #[test]
+    fn proof_number_search_proves_root_once() {
+        let config = SearchConfig {
+            endgame_empty_cells: 0,
+            ..SearchConfig::default()
+        };
+        let mut tree =
+            Tree::<Ucb1>::with_root_state(&config, player1_wins_with_20(), Ucb1::default());
+        tree.try_prove_root(None);
+        assert_eq!(tree.nodes[tree.root as usize].proof, Some(Proof::Win));
+        // only the winning child was added
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.best_explored_move(), 20);
+
+        let mut rng = SearchRng::from_seed(15);
+        let lost_state = iter::repeat_with(|| endgame::test::random_endgame(12, &mut rng))
+            .flatten()
+            .find(|state| EndgameSolver::new(12).solve(state) == -1)
+            .unwrap();
+        let mut tree = Tree::<Ucb1>::with_root_state(&config, lost_state, Ucb1::default());
+        tree.search_until(Instant::now() + Duration::from_millis(10));
+        assert_eq!(tree.nodes[tree.root as usize].proof, Some(Proof::Loss));
+        assert!(tree.root_proof_attempted);
+
+        let config = SearchConfig {
+            proof_number_budget: 0,
+            ..config
+        };
+        let mut tree =
+            Tree::<Ucb1>::with_root_state(&config, player1_wins_with_20(), Ucb1::default());
+        tree.try_prove_root(None);
+        assert_eq!(tree.nodes[tree.root as usize].proof, None);
+    }
+
+    #[test]
+    fn proof_number_search_skips_big_roots_without_time() {
+        let mut tree = Tree::default();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(tree.try_prove_root(Some(deadline)), None);
+        assert!(!tree.root_proof_attempted);
+
+        let deadline = Instant::now() + Tree::<Ucb1>::MIN_TIME_TO_PROVE_ANY_ROOT * 2;
+        assert_eq!(
+            tree.try_prove_root(Some(deadline)),
+            Some(ProofNumberResult::Unknown)
+        );
+        assert!(tree.root_proof_attempted);
+    }
+
+    #[test]
+    fn search_until_does_nothing_after_the_deadline() {
+        let mut tree = Tree::default();
+        tree.search_until(Instant::now());
+        assert_eq!(tree.nodes[tree.root as usize].visits, 0);
+        assert_eq!(tree.nodes.len(), 1);
+        assert!(!tree.root_proof_attempted);
+    }
+
+    fn assert_avoids_proven_losses<S: SelectionPolicy>(selection_policy: S) {
+        // player 2 has won boards 3 and 4 and wins board 5 (and the game) with 47,
+        // player 1 is forced into the empty board 0
+        let state = state_after(&[
+            56, 27, 64, 28, 72, 29, 60, 36, 70, 37, 80, 38, 12, 46, 22, 45,
+        ]);
+        assert_eq!(state.active_player(), Player::Player1);
+        assert_eq!(state.forced_board(), 0);
+
+        let mut tree =
+            Tree::<S>::with_root_state(&SearchConfig::default(), state, selection_policy);
+        tree.search_n(5_000);
+        for (move_, _, child) in tree.explored_root_children() {
+            // sends player 2 to board 5 or to a won board from where they can go anywhere
+            if [3, 4, 5].contains(&move_) {
+                assert_eq!(child.proof, Some(Proof::Win), "move {move_}");
+            }
+        }
+        assert!(![3, 4, 5].contains(&tree.best_explored_move()));
+    }
+
+    #[test]
+    fn solver_avoids_proven_losses() {
+        assert_avoids_proven_losses(Ucb1::default());
+    }
+
+    #[test]
+    fn all_selection_policies_search() {
+        assert_avoids_proven_losses(Ucb1Tuned);
+        assert_avoids_proven_losses(Puct::<UniformPrior>::default());
+
+        let mut tree = Tree::new_with_policy(&SearchConfig::default(), Ucb1Tuned);
+        tree.search_n(2_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 2_000);
+        let mut tree = Tree::from_moves_with_policy(
+            &SearchConfig::default(),
+            &[40],
+            Puct::<UniformPrior>::default(),
+        );
+        tree.search_n(2_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 2_000);
+    }
+
+    #[test]
+    fn searches_are_reproducible() {
+        let config = SearchConfig {
+            seed: 21,
+            ..SearchConfig::default()
+        };
+        // two trees on the same thread do not share a random stream
+        let (mut tree, mut other_tree) = (Tree::new(&config), Tree::new(&config));
+        tree.search_n(1_000);
+        other_tree.search_n(2_000);
+        tree.search_n(2_000);
+        other_tree.search_n(1_000);
+        assert_eq!(tree.root_move_stats(), other_tree.root_move_stats());
+        assert_eq!(tree.best_explored_move(), other_tree.best_explored_move());
+
+        let best_move = tree.best_explored_move();
+        let best_stats = tree
+            .root_move_stats()
+            .into_iter()
+            .find(|stats| stats.move_ == best_move)
+            .unwrap();
+        // pinned, a change of these means searches with the same seed no longer play the same
+        assert_eq!((best_move, best_stats.visits), (30, 97));
+
+        let mut reseeded_tree = Tree::new(&SearchConfig { seed: 22, ..config });
+        reseeded_tree.search_n(3_000);
+        assert_ne!(tree.root_move_stats(), reseeded_tree.root_move_stats());
+    }
+
+    #[test]
+    fn batched_playouts_count_once() {
+        let config = SearchConfig {
+            playouts_per_leaf: NonZeroU32::new(4).unwrap(),
+            ..SearchConfig::default()
+        };
+        let mut tree = Tree::new(&config);
+        tree.search_n(2_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 2_000);
+        for node in &tree.nodes[1..] {
+            // the mean of a batch of 4 results in -1, 0 and 1
+            assert_eq!(node.playout_visits, 1);
+            assert_eq!((node.playout_score * 4.0).fract(), 0.0);
+        }
+
+        // the buffer does not change which playouts are played
+        let mut buffered_tree = Tree::new(&SearchConfig {
+            reuse_simulation_buffer: true,
+            ..config
+        });
+        buffered_tree.search_n(2_000);
+        assert_eq!(tree.root_move_stats(), buffered_tree.root_move_stats());
+    }
+
+    #[test]
+    fn lane_playouts_count_once() {
+        let config = SearchConfig {
+            lane_playouts: true,
+            ..SearchConfig::default()
+        };
+        let mut tree = Tree::new(&config);
+        tree.search_n(2_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 2_000);
+        for node in &tree.nodes[1..] {
+            // the mean of a batch of LANES results in -1, 0 and 1
+            assert_eq!(node.playout_visits, 1);
+            assert_eq!((node.playout_score * LANES as f32).fract(), 0.0);
+        }
+        let best_move = tree.best_explored_move();
+        tree.apply_explored_move(best_move);
+        tree.search_n(1_000);
+
+        // the rollout policy decides, heuristic playouts are not played in lanes
+        let mut heuristic_tree = Tree::new(&config).with_rollout_policy(HeuristicRollout);
+        heuristic_tree.search_n(2_000);
+        let mut reference_tree =
+            Tree::new(&SearchConfig::default()).with_rollout_policy(HeuristicRollout);
+        reference_tree.search_n(2_000);
+        assert_eq!(
+            heuristic_tree.root_move_stats(),
+            reference_tree.root_move_stats()
+        );
+    }
+
+    #[test]
+    fn heuristic_rollout_search() {
+        let mut tree = Tree::default().with_rollout_policy(HeuristicRollout);
+        tree.search_n(2_000);
+        assert_eq!(tree.nodes[tree.root as usize].visits, 2_000);
+        let best_move = tree.best_explored_move();
+        tree.apply_explored_move(best_move);
+        tree.search_n(1_000);
+        tree.compact();
+        tree.search_n(1_000);
+    }
+
+    #[test]
+    fn transpositions_keep_statistics_consistent() {
+        let mut tree = Tree::default();
+        tree.search_n(30_000);
+
+        let mut n_parents = vec![0; tree.nodes.len()];
+        for (node_idx, node) in tree.nodes.iter().enumerate().skip(1) {
+            let edges = &tree.edges
+                [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+            let mut visits = node.playout_visits;
+            for edge in edges {
+                let Some(child_node) = edge.child_node else {
+                    assert_eq!(edge.visits, 0);
+                    continue;
+                };
+                let child = &tree.nodes[child_node.get() as usize];
+                // visits through other parents are never attributed to this edge
+                assert!(edge.visits <= child.visits, "node {node_idx}");
+                visits += edge.visits;
+                n_parents[child_node.get() as usize] += 1;
+            }
+            // a parent only counts the visits it made itself
+            assert_eq!(node.visits, visits, "node {node_idx}");
+            assert!((-1.0..=1.0).contains(&node.value), "node {node_idx}");
+        }
+        // the root is the only node without playouts of its own
+        let root = &tree.nodes[tree.root as usize];
+        assert_eq!(root.playout_visits, 0);
+        assert_eq!(root.visits, 30_000);
+
+        assert!(
+            n_parents.iter().any(|n_parents| *n_parents > 1),
+            "the search should have run into transpositions"
+        );
+    }
+
+    #[test]
+    fn root_proof_respects_the_memory_budget() {
+        let mut tree = Tree::<Ucb1>::with_root_state(
+            &SearchConfig::default(),
+            player1_wins_with_20(),
+            Ucb1::default(),
+        );
+        let n_nodes = tree.nodes.len();
+        // the search ran out of memory before
+        tree.degraded = true;
+        tree.apply_root_proof(ProofNumberResult::Win { move_: 20 });
+        assert_eq!(tree.nodes.len(), n_nodes);
+        assert_eq!(tree.nodes[tree.root as usize].proof, None);
+
+        // no space left for the winning child
+        tree.degraded = false;
+        tree.memory_budget = 0;
+        tree.apply_root_proof(ProofNumberResult::Win { move_: 20 });
+        assert_eq!(tree.nodes.len(), n_nodes);
+        assert!(tree.is_degraded());
+
+        // a linked child is proven even when degraded
+        let child_node_idx = tree.explore_edge(tree.root, 20);
+        tree.apply_root_proof(ProofNumberResult::Win { move_: 20 });
+        assert_eq!(tree.nodes[child_node_idx as usize].proof, Some(Proof::Loss));
+        assert_eq!(tree.nodes[tree.root as usize].proof, Some(Proof::Win));
+    }
+
+    #[test]
+    fn memory_budget_stops_growth() {
+        let config = SearchConfig {
+            initial_n_nodes: 1_000,
+            ..SearchConfig::default()
+        };
+        // exactly the reserved capacity
+        let config = SearchConfig {
+            memory_budget: Tree::new(&config).memory_used(),
+            ..config
+        };
+        let mut tree = Tree::new(&config);
+        let reserved_nodes = tree.nodes.capacity();
+        tree.search_n(200);
+        assert!(!tree.is_degraded());
+
+        tree.search_n(20_000);
+        assert!(tree.is_degraded());
+        assert!(tree.search_stats().degraded);
+        assert!(tree.memory_used() <= config.memory_budget);
+        // the search keeps going, only the tree does not grow
+        assert_eq!(tree.nodes[tree.root as usize].visits, 20_200);
+
+        let best_move = tree.best_explored_move();
+        tree.apply_explored_move(best_move);
+        tree.compact();
+        assert!(!tree.is_degraded());
+        tree.search_n(1_000);
+        assert!(tree.memory_used() <= config.memory_budget);
+        assert_eq!(tree.nodes.capacity(), reserved_nodes);
+    }
+
+    #[test]
+    fn reserves_within_the_memory_budget() {
+        let tree = Tree::default();
+        assert!(tree.memory_used() <= SearchConfig::default().memory_budget);
+
+        let config = SearchConfig {
+            memory_budget: 64 * 1024 * 1024,
+            ..SearchConfig::default()
+        };
+        let mut tree = Tree::new(&config);
+        let reserved = tree.memory_used();
+        assert!(reserved <= config.memory_budget);
+        // the solvers reserve their memory up front as well
+        tree.search_until(Instant::now() + Duration::from_millis(20));
+        assert!(tree.memory_used() <= config.memory_budget);
+    }
 }
@@ -0,0 +1,480 @@
+use std::time::Instant;
+
+use crate::{consts, tree::node_state::NodeState, types::BoardState};
+
+/// evaluation in favor of the player to move
+type Eval = i32;
+
+/// won games score this minus the plies it takes, so that faster wins are preferred
+const WIN: Eval = 1_000_000;
+/// wider than any score so that the first move always raises alpha
+const INFINITY: Eval = WIN + 1;
+
+/// weights of the handcrafted evaluation
+const WON_BOARD: Eval = 100;
+const CENTER_BOARD: Eval = 30;
+const SUPER_BOARD_THREAT: Eval = 60;
+const BOARD_THREAT: Eval = 10;
+const CENTER_CELL: Eval = 3;
+/// the player to move may play anywhere
+const FREE_CHOICE: Eval = 20;
+/// the player to move is sent to a board they can win right away
+const FORCED_INTO_THREAT: Eval = 15;
+
+const CENTER: u8 = 4;
+const FULL_BOARD: BoardState = 0b1_1111_1111;
+
+/// entries of the transposition table of [`AlphaBetaEngine::new`]
+const DEFAULT_TABLE_ENTRIES: usize = 1 << 20;
+
+/// how a stored score relates to the true score of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TableEntry {
+    /// zobrist hash of the position
+    key: u64,
+    depth: u32,
+    /// decided games relative to the position, see [`score_to_table`]
+    score: Eval,
+    bound: Bound,
+    best_move: u8,
+}
+
+/// the score of a decided game counts the plies from the root of the search, the table stores
+/// them from the position itself so that the entry stays valid at any ply and across searches
+const fn score_to_table(score: Eval, ply: u32) -> Eval {
+    if score > WIN - consts::N_CELLS_NESTED as Eval {
+        score + ply as Eval
+    } else if score < -(WIN - consts::N_CELLS_NESTED as Eval) {
+        score - ply as Eval
+    } else {
+        score
+    }
+}
+
+/// inverse of [`score_to_table`]
+const fn score_from_table(score: Eval, ply: u32) -> Eval {
+    if score > WIN - consts::N_CELLS_NESTED as Eval {
+        score - ply as Eval
+    } else if score < -(WIN - consts::N_CELLS_NESTED as Eval) {
+        score + ply as Eval
+    } else {
+        score
+    }
+}
+
+/// Fixed-size transposition table indexed by the zobrist hash, a new entry always replaces the
+/// one in its slot.
+#[derive(Debug, Clone)]
+struct TranspositionTable {
+    /// power of two
+    slots: Vec<Option<TableEntry>>,
+}
+
+impl TranspositionTable {
+    fn with_entries(n_entries: usize) -> Self {
+        let n_slots = if n_entries.is_power_of_two() {
+            n_entries
+        } else {
+            // round down to stay within the memory budget
+            (n_entries.next_power_of_two() / 2).max(1)
+        };
+        Self {
+            slots: vec![None; n_slots],
+        }
+    }
+
+    fn slot(&self, state: &NodeState) -> usize {
+        state.zobrist() as usize & (self.slots.len() - 1)
+    }
+
+    fn get(&self, state: &NodeState) -> Option<TableEntry> {
+        self.slots[self.slot(state)].filter(|entry| entry.key == state.zobrist())
+    }
+
+    fn insert(&mut self, entry: TableEntry) {
+        let slot = entry.key as usize & (self.slots.len() - 1);
+        self.slots[slot] = Some(entry);
+    }
+}
+
+/// the search ran out of time, the current iteration is discarded
+struct OutOfTime;
+
+/// Iterative deepening alpha-beta search with a handcrafted evaluation, an alternative to the
+/// Monte Carlo tree search of [`crate::tree::Tree`].
+///
+/// Games that end without a winner are decided by [`NodeState::decide_draw`], like in the tree.
+pub struct AlphaBetaEngine {
+    state: NodeState,
+    table: TranspositionTable,
+    /// best move of the deepest completed iteration of the last search
+    best_move: Option<u8>,
+    /// best move at the root of the last iteration, the table entry might be replaced
+    root_best_move: u8,
+    completed_depth: u32,
+    /// nodes visited by the last search
+    nodes: u64,
+    deadline: Instant,
+}
+
+impl Default for AlphaBetaEngine {
+    fn default() -> Self {
+        Self::with_state(NodeState::empty(), DEFAULT_TABLE_ENTRIES)
+    }
+}
+
+impl AlphaBetaEngine {
+    /// how many nodes are visited between two looks at the clock
+    const NODES_PER_TIME_CHECK: u64 = 1024;
+
+    /// starts at the empty board with player 1 to move
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// like [`Self::new`], but the transposition table occupies at most `memory_budget` bytes,
+    /// see [`crate::config::SearchConfig::memory_budget`]
+    pub fn with_memory_budget(memory_budget: usize) -> Self {
+        Self::with_state(
+            NodeState::empty(),
+            memory_budget / size_of::<Option<TableEntry>>(),
+        )
+    }
+
+    pub(super) fn with_state(state: NodeState, n_table_entries: usize) -> Self {
+        Self {
+            state,
+            table: TranspositionTable::with_entries(n_table_entries),
+            best_move: None,
+            root_best_move: 0,
+            completed_depth: 0,
+            nodes: 0,
+            deadline: Instant::now(),
+        }
+    }
+
+    /// approximate number of bytes occupied by the transposition table
+    pub fn memory_used(&self) -> usize {
+        self.table.slots.len() * size_of::<Option<TableEntry>>()
+    }
+
+    /// starts a new game at the empty board, the transposition table stays valid
+    pub fn restart(&mut self) {
+        self.state = NodeState::empty();
+        self.best_move = None;
+        self.completed_depth = 0;
+    }
+
+    /// plays the move (by either player) on the board the engine searches from
+    pub fn apply_move(&mut self, move_: u8) {
+        debug_assert!(
+            self.state.available_in_board_or_fallback().get() & (1 << move_) != 0,
+            "move {move_} is not available"
+        );
+        self.state = self.state.apply_move(move_).0;
+        self.best_move = None;
+        self.completed_depth = 0;
+    }
+
+    /// Deepens the search one ply at a time until `deadline`, the iteration which runs out of
+    /// time is discarded. At least the first ply is always completed.
+    pub fn search_until(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        self.nodes = 0;
+        self.best_move = None;
+        self.completed_depth = 0;
+
+        let n_empty_cells = self.state.n_empty_cells();
+        for depth in 1..=n_empty_cells {
+            let state = self.state;
+            match self.negamax(&state, depth, 0, -INFINITY, INFINITY) {
+                Ok(score) => {
+                    self.best_move = Some(self.root_best_move);
+                    self.completed_depth = depth;
+                    // the rest of the game is known
+                    if score.abs() > WIN - consts::N_CELLS_NESTED as Eval {
+                        break;
+                    }
+                }
+                Err(OutOfTime) => break,
+            }
+        }
+    }
+
+    /// the best move found by the last search
+    pub fn best_move(&self) -> u8 {
+        self.best_move
+            .expect("search_until must be called before asking for the best move")
+    }
+
+    /// depth of the deepest completed iteration of the last search
+    pub fn completed_depth(&self) -> u32 {
+        self.completed_depth
+    }
+
+    /// nodes visited by the last search
+    pub fn nodes_searched(&self) -> u64 {
+        self.nodes
+    }
+
+    /// # Returns
+    /// the score in favor of the player to move, [`OutOfTime`] if the deadline passed (except
+    /// while searching the first ply)
+    fn negamax(
+        &mut self,
+        state: &NodeState,
+        depth: u32,
+        ply: u32,
+        mut alpha: Eval,
+        beta: Eval,
+    ) -> Result<Eval, OutOfTime> {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(Self::NODES_PER_TIME_CHECK)
+            && self.completed_depth != 0
+            && Instant::now() >= self.deadline
+        {
+            return Err(OutOfTime);
+        }
+
+        let available = state.available_in_board_or_fallback().get();
+        if available == 0 {
+            return Ok(state.decide_draw(state.active_player()) * (WIN - ply as Eval));
+        }
+        if depth == 0 {
+            return Ok(evaluate(state));
+        }
+
+        let table_entry = self.table.get(state);
+        if let Some(entry) = table_entry.filter(|entry| entry.depth >= depth && ply != 0) {
+            let score = score_from_table(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return Ok(score),
+                Bound::Lower if score >= beta => return Ok(score),
+                Bound::Upper if score <= alpha => return Ok(score),
+                _ => {}
+            }
+        }
+
+        // the best move of a previous iteration first, it is the most likely to cut off
+        let first_move = table_entry
+            .map(|entry| entry.best_move)
+            .filter(|move_| available & (1 << move_) != 0);
+        let other_moves = first_move.map_or(available, |move_| available & !(1 << move_));
+        let moves = first_move
+            .into_iter()
+            .chain(BitIter(other_moves).map(|move_| move_ as u8));
+
+        let alpha_before = alpha;
+        let (mut best_score, mut best_move) = (-INFINITY, 0);
+        for move_ in moves {
+            let (child_state, has_won) = state.apply_move(move_);
+            let score = if has_won {
+                WIN - (ply + 1) as Eval
+            } else {
+                -self.negamax(&child_state, depth - 1, ply + 1, -beta, -alpha)?
+            };
+            if score > best_score {
+                (best_score, best_move) = (score, move_);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_before {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(TableEntry {
+            key: state.zobrist(),
+            depth,
+            score: score_to_table(best_score, ply),
+            bound,
+            best_move,
+        });
+        if ply == 0 {
+            self.root_best_move = best_move;
+        }
+        Ok(best_score)
+    }
+}
+
+/// iterates the indices of the set bits
+struct BitIter(u128);
+
+impl Iterator for BitIter {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let idx = self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+        Some(idx)
+    }
+}
+
+/// lines of a 3x3 board where `own` has two cells and the third is in `free`
+fn count_threats(own: BoardState, free: BoardState) -> Eval {
+    consts::WINNER_MASKS_1BIT
+        .iter()
+        .filter(|mask| {
+            let own_in_line = own & **mask;
+            own_in_line.count_ones() == 2 && (**mask & !own_in_line) & free != 0
+        })
+        .count() as Eval
+}
+
+/// Handcrafted evaluation in favor of the player to move: won boards (the centre board counting
+/// extra), two-in-a-row threats on the super board and on the open boards, the centre cells and
+/// the board the player to move was sent to.
+fn evaluate(state: &NodeState) -> Eval {
+    let player = state.active_player();
+    let won = [player, player.other()].map(|player| state.super_board_for_player(player));
+    let open_boards = !(won[0] | won[1] | state.drawn_boards()) & FULL_BOARD;
+
+    let mut eval = 0;
+    for (sign, side) in [(1, 0), (-1, 1)] {
+        let side_player = [player, player.other()][side];
+        let mut side_eval = won[side].count_ones() as Eval * WON_BOARD
+            + count_threats(won[side], open_boards) * SUPER_BOARD_THREAT;
+        if won[side] & (1 << CENTER) != 0 {
+            side_eval += CENTER_BOARD;
+        }
+        for board_idx in (0..consts::N_BOARDS as u8).filter(|idx| open_boards & (1 << idx) != 0) {
+            let own = state.get_player_board(side_player, board_idx).get();
+            let other = state.get_player_board(side_player.other(), board_idx).get();
+            side_eval += count_threats(own, !(own | other) & FULL_BOARD) * BOARD_THREAT;
+            if own & (1 << CENTER) != 0 {
+                side_eval += CENTER_CELL;
+            }
+        }
+        eval += sign * side_eval;
+    }
+
+    // the opponent decided where the player to move has to play
+    let forced_board = state.forced_board();
+    if forced_board as u32 >= consts::N_BOARDS || open_boards & (1 << forced_board) == 0 {
+        eval += FREE_CHOICE;
+    } else {
+        let own = state.get_player_board(player, forced_board).get();
+        let other = state.get_player_board(player.other(), forced_board).get();
+        if count_threats(own, !(own | other) & FULL_BOARD) != 0 {
+            eval += FORCED_INTO_THREAT;
+        }
+    }
+    eval
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        tree::{
+            alpha_beta::{AlphaBetaEngine, WIN, evaluate, score_from_table, score_to_table},
+            node_state::NodeState,
+        },
+        types::Player,
+    };
+
+    fn state_after(moves: &[u8]) -> NodeState {
+        moves.iter().fold(NodeState::empty(), |state, move_| {
+            state.apply_move(*move_).0
+        })
+    }
+
+    #[test]
+    fn finds_immediate_win() {
+        // player 1 to move, has won boards 0 and 1 and wins board 2 (and the game) with 20
+        let state = state_after(&[0, 36, 1, 49, 2, 62, 9, 73, 10, 66, 11, 28, 18, 57, 19, 74]);
+        let mut engine = AlphaBetaEngine::with_state(state, 1 << 16);
+        engine.search_until(Instant::now() + Duration::from_millis(50));
+        assert_eq!(engine.best_move(), 20);
+        assert!(engine.completed_depth() >= 1);
+    }
+
+    #[test]
+    fn avoids_immediate_loss() {
+        // player 2 has won boards 3 and 4 and wins board 5 (and the game) with 47,
+        // player 1 is forced into the empty board 0
+        let state = state_after(&[
+            56, 27, 64, 28, 72, 29, 60, 36, 70, 37, 80, 38, 12, 46, 22, 45,
+        ]);
+        assert_eq!(state.active_player(), Player::Player1);
+        let mut engine = AlphaBetaEngine::with_state(state, 1 << 16);
+        engine.search_until(Instant::now() + Duration::from_millis(50));
+        // sends player 2 to board 5 or to a won board from where they can go anywhere
+        assert!(![3, 4, 5].contains(&engine.best_move()));
+        assert!(engine.completed_depth() >= 2);
+    }
+
+    #[test]
+    fn table_scores_of_decided_games_are_relative_to_the_position() {
+        // a win 2 plies after a position that is 3 plies below the root
+        let stored = score_to_table(WIN - 5, 3);
+        assert_eq!(stored, WIN - 2);
+        // reached again as the root of a later search
+        assert_eq!(score_from_table(stored, 0), WIN - 2);
+        assert_eq!(score_from_table(stored, 3), WIN - 5);
+        assert_eq!(
+            score_from_table(score_to_table(-(WIN - 7), 4), 1),
+            -(WIN - 4)
+        );
+        // evaluations are not touched
+        assert_eq!(score_to_table(250, 6), 250);
+        assert_eq!(score_from_table(-250, 6), -250);
+    }
+
+    #[test]
+    fn table_stays_within_the_memory_budget() {
+        let memory_budget = 1024 * 1024;
+        let engine = AlphaBetaEngine::with_memory_budget(memory_budget);
+        assert!(engine.memory_used() <= memory_budget);
+        assert!(engine.memory_used() > memory_budget / 2);
+    }
+
+    #[test]
+    fn evaluation_favors_won_boards() {
+        // player 1 won board 0, player 2 played scattered moves, player 1 to move
+        let state = state_after(&[0, 36, 1, 49, 2, 62]);
+        assert_eq!(state.active_player(), Player::Player1);
+        assert!(evaluate(&state) > 0);
+        let state = state
+            .apply_move(
+                state
+                    .available_in_board_or_fallback()
+                    .get()
+                    .trailing_zeros() as u8,
+            )
+            .0;
+        assert!(evaluate(&state) < 0);
+    }
+
+    #[test]
+    fn respects_the_deadline() {
+        let mut engine = AlphaBetaEngine::new();
+        let start = Instant::now();
+        engine.search_until(start + Duration::from_millis(30));
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert!(engine.completed_depth() >= 1);
+        assert!(engine.nodes_searched() > 0);
+
+        let best_move = engine.best_move();
+        engine.apply_move(best_move);
+        engine.search_until(Instant::now() + Duration::from_millis(10));
+        assert_eq!(engine.best_move() / 9, best_move % 9);
+    }
+}
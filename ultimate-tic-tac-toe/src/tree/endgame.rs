@@ -0,0 +1,297 @@
+use std::{collections::HashMap, time::Instant};
+
+use crate::tree::{MonteCarloScore, node_state::NodeState};
+
+/// the cache is cleared once it grows beyond this
+const MAX_CACHE_ENTRIES: usize = 1 << 18;
+
+/// how a cached score relates to the true score of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// the true score is at least the cached one (the search failed high)
+    Lower,
+    /// the true score is at most the cached one (the search failed low)
+    Upper,
+}
+
+/// the solve ran out of nodes or time, its result is discarded
+struct OutOfBudget;
+
+/// Exact negamax solver with alpha-beta pruning for positions with few empty cells.
+///
+/// Scores are -1, 0 or 1 in favor of the player to move. A game that ends without a winner is
+/// decided by [`NodeState::decide_draw`] like everywhere else in the tree, a settled one (see
+/// [`NodeState::settled_score`]) is scored without searching it.
+#[derive(Debug, Clone)]
+pub(super) struct EndgameSolver {
+    /// positions with at most this many empty cells are solved
+    max_empty_cells: u32,
+    /// positions are independent of how they were reached, so the cache stays valid across
+    /// searches and roots
+    cache: HashMap<NodeState, (MonteCarloScore, Bound)>,
+    /// nodes the current solve may still visit
+    nodes_left: u32,
+    deadline: Option<Instant>,
+}
+
+impl EndgameSolver {
+    /// nodes a single solve may visit, the slowest solves take a few ms
+    const NODE_BUDGET: u32 = 10_000;
+    /// how many nodes are visited between two looks at the clock
+    const NODES_PER_TIME_CHECK: u32 = 1024;
+
+    pub(super) fn new(max_empty_cells: u32) -> Self {
+        // a solve may add its whole budget before the cache is cleared, it never has to grow
+        let cache_capacity = if max_empty_cells == 0 {
+            0
+        } else {
+            MAX_CACHE_ENTRIES + Self::NODE_BUDGET as usize
+        };
+        Self {
+            max_empty_cells,
+            cache: HashMap::with_capacity(cache_capacity),
+            nodes_left: 0,
+            deadline: None,
+        }
+    }
+
+    /// approximate number of bytes occupied by the cache
+    pub(super) fn memory_used(&self) -> usize {
+        // one control byte per entry
+        self.cache.capacity() * (size_of::<(NodeState, (MonteCarloScore, Bound))>() + 1)
+    }
+
+    /// forgets all cached positions, e.g. once they can no longer be reached
+    pub(super) fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// # Arguments
+    /// - `deadline`: gives up once reached, on top of [`Self::NODE_BUDGET`]
+    /// # Returns
+    /// the score in favor of the player to move if the position is small enough to be solved
+    /// within the budget
+    pub(super) fn try_solve(
+        &mut self,
+        state: &NodeState,
+        deadline: Option<Instant>,
+    ) -> Option<MonteCarloScore> {
+        if state.n_empty_cells() > self.max_empty_cells {
+            return None;
+        }
+        if self.cache.len() > MAX_CACHE_ENTRIES {
+            self.cache.clear();
+        }
+        self.nodes_left = Self::NODE_BUDGET;
+        self.deadline = deadline;
+        self.negamax(state, -1, 1).ok()
+    }
+
+    /// score in favor of the player to move without any budget, the game must not be over
+    /// already
+    #[cfg(test)]
+    pub(super) fn solve(&mut self, state: &NodeState) -> MonteCarloScore {
+        self.nodes_left = u32::MAX;
+        self.deadline = None;
+        self.negamax(state, -1, 1)
+            .unwrap_or_else(|OutOfBudget| unreachable!("the solve has no budget"))
+    }
+
+    /// # Returns
+    /// the score in favor of the player to move, [`OutOfBudget`] if the nodes or the time ran out
+    fn negamax(
+        &mut self,
+        state: &NodeState,
+        mut alpha: MonteCarloScore,
+        mut beta: MonteCarloScore,
+    ) -> Result<MonteCarloScore, OutOfBudget> {
+        let mut available = state.available_in_board_or_fallback().get();
+        if available == 0 {
+            return Ok(state.decide_draw(state.active_player()));
+        }
+        if let Some(score) = state.settled_score(state.active_player()) {
+            return Ok(score);
+        }
+
+        if self.nodes_left == 0 {
+            return Err(OutOfBudget);
+        }
+        self.nodes_left -= 1;
+        if self.nodes_left.is_multiple_of(Self::NODES_PER_TIME_CHECK)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(OutOfBudget);
+        }
+
+        if let Some(&(score, bound)) = self.cache.get(state) {
+            match bound {
+                Bound::Exact => return Ok(score),
+                Bound::Lower => alpha = alpha.max(score),
+                Bound::Upper => beta = beta.min(score),
+            }
+            if alpha >= beta {
+                return Ok(score);
+            }
+        }
+
+        let alpha_before = alpha;
+        let mut best_score = MonteCarloScore::MIN;
+        while available != 0 {
+            let move_ = available.trailing_zeros() as u8;
+            available &= available - 1;
+
+            let (child_state, has_won) = state.apply_move(move_);
+            let score = if has_won {
+                1
+            } else {
+                -self.negamax(&child_state, -beta, -alpha)?
+            };
+            best_score = best_score.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_before {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.cache.insert(*state, (best_score, bound));
+        Ok(best_score)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use crate::{
+        consts,
+        rng::SearchRng,
+        tree::{MonteCarloScore, endgame::EndgameSolver, node_state::NodeState},
+    };
+
+    /// negamax without pruning or caching
+    fn brute_force(state: &NodeState) -> MonteCarloScore {
+        let mut available = state.available_in_board_or_fallback().get();
+        if available == 0 {
+            return state.decide_draw(state.active_player());
+        }
+        let mut best_score = MonteCarloScore::MIN;
+        while available != 0 {
+            let move_ = available.trailing_zeros() as u8;
+            available &= available - 1;
+            let (child_state, has_won) = state.apply_move(move_);
+            let score = if has_won {
+                1
+            } else {
+                -brute_force(&child_state)
+            };
+            best_score = best_score.max(score);
+        }
+        best_score
+    }
+
+    /// plays random moves until at most `max_empty_cells` are left, None if the game ended
+    pub(in crate::tree) fn random_endgame(
+        max_empty_cells: u32,
+        rng: &mut SearchRng,
+    ) -> Option<NodeState> {
+        let mut state = NodeState::empty();
+        while state.n_empty_cells() > max_empty_cells {
+            let available = state.available_in_board_or_fallback().get();
+            let nth = rng.rand_in_move_range_exclusive(available.count_ones() as u8);
+            let move_ = crate::bitmagic::index_of_nth_setbit(available, nth) as u8;
+            let (child_state, has_won) = state.apply_move(move_);
+            if has_won || child_state.all_boards_decided() {
+                return None;
+            }
+            state = child_state;
+        }
+        Some(state)
+    }
+
+    /// plays random games until a move settles one (see [`NodeState::settled_score`]) before it
+    /// is won or every board is decided
+    /// # Returns
+    /// the position before that move and the move
+    pub(in crate::tree) fn random_settling_move(rng: &mut SearchRng) -> (NodeState, u8) {
+        loop {
+            let mut state = NodeState::empty();
+            loop {
+                let available = state.available_in_board_or_fallback().get();
+                let nth = rng.rand_in_move_range_exclusive(available.count_ones() as u8);
+                let move_ = crate::bitmagic::index_of_nth_setbit(available, nth) as u8;
+                let (child_state, has_won) = state.apply_move(move_);
+                if has_won || child_state.all_boards_decided() {
+                    break;
+                }
+                if child_state.is_settled() {
+                    return (state, move_);
+                }
+                state = child_state;
+            }
+        }
+    }
+
+    #[test]
+    fn scores_settled_positions_without_searching() {
+        let mut rng = SearchRng::from_seed(14);
+        let (parent_state, move_) = random_settling_move(&mut rng);
+        let state = parent_state.apply_move(move_).0;
+        let mut solver = EndgameSolver::new(consts::N_CELLS_NESTED);
+        assert_eq!(
+            solver.try_solve(&state, None),
+            state.settled_score(state.active_player())
+        );
+        assert_eq!(solver.nodes_left, EndgameSolver::NODE_BUDGET);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = SearchRng::from_seed(14);
+        let mut solver = EndgameSolver::new(8);
+        let mut scores = [0; 3];
+        for state in (0..500)
+            .filter_map(|_| random_endgame(8, &mut rng))
+            .take(100)
+        {
+            let score = solver.solve(&state);
+            assert_eq!(score, brute_force(&state), "{state:?}");
+            assert_eq!(solver.try_solve(&state, None), Some(score));
+            scores[(score + 1) as usize] += 1;
+        }
+        // the positions are not all trivially decided the same way
+        assert!(scores.iter().filter(|count| **count != 0).count() >= 2);
+        assert!(!solver.cache.is_empty());
+    }
+
+    #[test]
+    fn ignores_positions_with_too_many_empty_cells() {
+        let mut solver = EndgameSolver::new(9);
+        assert_eq!(solver.try_solve(&NodeState::empty(), None), None);
+        assert!(solver.cache.is_empty());
+    }
+
+    #[test]
+    fn gives_up_when_out_of_budget() {
+        let mut rng = SearchRng::from_seed(14);
+        let state = std::iter::repeat_with(|| random_endgame(40, &mut rng))
+            .flatten()
+            .next()
+            .unwrap();
+        let mut solver = EndgameSolver::new(81);
+        assert_eq!(solver.try_solve(&state, None), None);
+        assert_eq!(solver.nodes_left, 0);
+
+        let mut solver = EndgameSolver::new(81);
+        let deadline = std::time::Instant::now();
+        assert_eq!(solver.try_solve(&state, Some(deadline)), None);
+        assert!(solver.nodes_left > 0);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{collections::HashSet, fmt, num::NonZero};
+
+use crate::{
+    consts,
+    tree::{
+        NO_MOVE_FORCED, NodeIdx, Proof, Tree, selection::SelectionPolicy, simulation::RolloutPolicy,
+    },
+    types::Player,
+};
+
+/// where to cut off an export of the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportLimits {
+    /// nodes deeper than this (relative to the root) are left out
+    pub max_depth: u32,
+    /// edges chosen less often than this (and the nodes only reachable through them) are left out
+    pub min_visits: u32,
+}
+
+impl Default for ExportLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            min_visits: 1,
+        }
+    }
+}
+
+struct ExportedEdge {
+    parent: NodeIdx,
+    child: NodeIdx,
+    move_: u8,
+    visits: u32,
+}
+
+/// Identifies a node by the zobrist hash of its state, which only depends on the position.
+/// The same position therefore gets the same id in every search, which makes exports diffable.
+struct NodeId(u64);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "n{:016x}", self.0)
+    }
+}
+
+const fn player_number(player: Player) -> u8 {
+    player as u8 + 1
+}
+
+fn proof_name(proof: Proof) -> &'static str {
+    match proof {
+        Proof::Loss => "loss",
+        Proof::Draw => "draw",
+        Proof::Win => "win",
+    }
+}
+
+impl<S: SelectionPolicy, R: RolloutPolicy> Tree<S, R> {
+    fn node_id(&self, node_idx: NodeIdx) -> NodeId {
+        NodeId(self.nodes[node_idx as usize].game_state.zobrist())
+    }
+
+    /// breadth first from the root, every node (with its depth) only once even if it is reached
+    /// through several transpositions, edges in move order
+    fn exported_subgraph(&self, limits: ExportLimits) -> (Vec<(NodeIdx, u32)>, Vec<ExportedEdge>) {
+        let mut nodes = vec![(self.root, 0)];
+        let mut edges = Vec::new();
+        let mut seen = HashSet::from([self.root]);
+        let mut next = 0;
+        while let Some(&(node_idx, depth)) = nodes.get(next) {
+            next += 1;
+            if depth == limits.max_depth {
+                continue;
+            }
+            let node = &self.nodes[node_idx as usize];
+            let node_edges = &self.edges
+                [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+            for edge in node_edges
+                .iter()
+                .filter(|edge| edge.visits != 0 && edge.visits >= limits.min_visits)
+            {
+                let Some(child) = edge.child_node.map(NonZero::get) else {
+                    continue;
+                };
+                if seen.insert(child) {
+                    nodes.push((child, depth + 1));
+                }
+                edges.push(ExportedEdge {
+                    parent: node_idx,
+                    child,
+                    move_: edge.move_,
+                    visits: edge.visits,
+                });
+            }
+        }
+        (nodes, edges)
+    }
+
+    /// Writes the subtree under the root as a Graphviz digraph.
+    ///
+    /// Nodes show the player to move, the forced board, the won boards (X = player 1,
+    /// O = player 2, col-major like the board indices), the visits and the mean score in favor of
+    /// the player to move at the node. Edges show the move and how often it was chosen.
+    pub fn write_dot(&self, out: &mut impl fmt::Write, limits: ExportLimits) -> fmt::Result {
+        let (nodes, edges) = self.exported_subgraph(limits);
+        writeln!(out, "digraph mcts {{")?;
+        writeln!(out, "  node [shape=box, fontname=\"monospace\"];")?;
+        for (node_idx, depth) in nodes {
+            let node = &self.nodes[node_idx as usize];
+            let state = &node.game_state;
+            write!(
+                out,
+                "  {} [label=\"depth {depth}\\nto move: {}\\nforced: ",
+                self.node_id(node_idx),
+                player_number(state.active_player()),
+            )?;
+            match state.forced_board() {
+                NO_MOVE_FORCED => write!(out, "-")?,
+                board_idx => write!(out, "{board_idx}")?,
+            }
+            let [won_by_player1, won_by_player2] = [Player::Player1, Player::Player2]
+                .map(|player| state.super_board_for_player(player));
+            for row in 0..consts::ROWS {
+                write!(out, "\\n")?;
+                for col in 0..consts::COLS {
+                    let board_bit = 1 << (row + consts::COLS * col);
+                    let symbol = if won_by_player1 & board_bit != 0 {
+                        'X'
+                    } else if won_by_player2 & board_bit != 0 {
+                        'O'
+                    } else {
+                        '.'
+                    };
+                    write!(out, "{symbol}")?;
+                }
+            }
+            write!(out, "\\nvisits: {}\\nscore: {:.3}", node.visits, node.value)?;
+            if let Some(proof) = node.proof {
+                write!(out, "\\nproof: {}", proof_name(proof))?;
+            }
+            writeln!(out, "\"];")?;
+        }
+        for edge in edges {
+            writeln!(
+                out,
+                "  {} -> {} [label=\"{} ({})\"];",
+                self.node_id(edge.parent),
+                self.node_id(edge.child),
+                edge.move_,
+                edge.visits,
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+
+    /// Writes the subtree under the root as JSON:
+    /// `{"root", "nodes": [...], "edges": [...]}`.
+    ///
+    /// Node ids only depend on the position, so the exports of two searches of the same position
+    /// can be diffed node by node. `score` is in favor of the node's `active_player`, `null` if
+    /// it is not finite. `super_boards` holds the won boards of player 1 and 2 as col-major
+    /// bitsets, `forced_board` is `null` if the player to move can play anywhere.
+    pub fn write_json(&self, out: &mut impl fmt::Write, limits: ExportLimits) -> fmt::Result {
+        let (nodes, edges) = self.exported_subgraph(limits);
+        write!(
+            out,
+            "{{\"root\":\"{}\",\"nodes\":[",
+            self.node_id(self.root),
+        )?;
+        for (i, (node_idx, depth)) in nodes.into_iter().enumerate() {
+            let node = &self.nodes[node_idx as usize];
+            let state = &node.game_state;
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "{{\"id\":\"{}\",\"depth\":{depth},\"active_player\":{},\"forced_board\":",
+                self.node_id(node_idx),
+                player_number(state.active_player()),
+            )?;
+            match state.forced_board() {
+                NO_MOVE_FORCED => write!(out, "null")?,
+                board_idx => write!(out, "{board_idx}")?,
+            }
+            write!(
+                out,
+                ",\"super_boards\":[{},{}],\"visits\":{},\"score\":",
+                state.super_board_for_player(Player::Player1),
+                state.super_board_for_player(Player::Player2),
+                node.visits,
+            )?;
+            // JSON has no NaN or infinity
+            if node.value.is_finite() {
+                write!(out, "{}", node.value)?;
+            } else {
+                write!(out, "null")?;
+            }
+            write!(out, ",\"proof\":")?;
+            match node.proof {
+                Some(proof) => write!(out, "\"{}\"}}", proof_name(proof))?,
+                None => write!(out, "null}}")?,
+            }
+        }
+        write!(out, "],\"edges\":[")?;
+        for (i, edge) in edges.into_iter().enumerate() {
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "{{\"from\":\"{}\",\"to\":\"{}\",\"move\":{},\"visits\":{}}}",
+                self.node_id(edge.parent),
+                self.node_id(edge.child),
+                edge.move_,
+                edge.visits,
+            )?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::SearchConfig,
+        tree::{Tree, export::ExportLimits},
+    };
+
+    #[test]
+    fn exports_respect_limits_and_are_reproducible() {
+        let config = SearchConfig::default();
+        let limits = ExportLimits {
+            max_depth: 2,
+            min_visits: 20,
+        };
+        let export = || {
+            let mut tree = Tree::new(&config);
+            tree.search_n(5_000);
+            let (mut dot, mut json) = (String::new(), String::new());
+            tree.write_dot(&mut dot, limits).unwrap();
+            tree.write_json(&mut json, limits).unwrap();
+            (tree, dot, json)
+        };
+        let (tree, dot, json) = export();
+
+        let (nodes, edges) = tree.exported_subgraph(limits);
+        assert!(nodes.iter().all(|(_, depth)| *depth <= limits.max_depth));
+        assert!(nodes.iter().any(|(_, depth)| *depth == limits.max_depth));
+        assert!(edges.iter().all(|edge| edge.visits >= limits.min_visits));
+        // rarely chosen root moves are cut off
+        assert!(edges.iter().filter(|edge| edge.parent == tree.root).count() < 81);
+
+        assert!(dot.starts_with("digraph mcts {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(dot.matches("->").count(), edges.len());
+        assert_eq!(dot.matches("[label=\"depth").count(), nodes.len());
+
+        assert!(json.starts_with("{\"root\":\"n"));
+        assert!(json.ends_with("]}\n"));
+        assert_eq!(json.matches("\"from\"").count(), edges.len());
+        assert_eq!(json.matches("\"depth\"").count(), nodes.len());
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert_eq!(json.matches('[').count(), json.matches(']').count());
+
+        // the same seed searches the same tree, so the exports are identical
+        let (_, dot_again, json_again) = export();
+        assert_eq!(dot, dot_again);
+        assert_eq!(json, json_again);
+    }
+
+    #[test]
+    fn json_has_no_non_finite_scores() {
+        let mut tree = Tree::new(&SearchConfig::default());
+        tree.search_n(100);
+        let root = tree.root as usize;
+        tree.nodes[root].value = f32::NAN;
+        let mut json = String::new();
+        tree.write_json(&mut json, ExportLimits::default()).unwrap();
+        assert!(json.contains("\"score\":null,"));
+        assert!(!json.contains("NaN"));
+    }
+}
@@ -0,0 +1,570 @@
+use crate::{
+    board::one_bit::OneBitBoard,
+    consts,
+    rng::SearchRng,
+    tree::{
+        MonteCarloScore, NO_MOVE_FORCED,
+        simulation::{self, SimulationState},
+    },
+    types::{Aligned32, BoardState, Player},
+};
+
+/// playouts advanced together by a [`LaneSimulation`], 4 * 64bit fill one AVX2 register
+pub const LANES: usize = 4;
+
+/// One board col-major bitset per lane, split into its low and high 64 bits so each half of all
+/// lanes can be loaded into a single 256bit register.
+type LaneBitsets = [Aligned32<u64, LANES>; 2];
+
+/// the index of the lowest cell of a sub-board per lane, see [`board_shift`]
+type LaneShifts = Aligned32<u64, LANES>;
+
+const BOARD_MASK: u128 = 0b1_1111_1111;
+/// shifts a sub-board out of the grid, for lanes without a move
+const NO_BOARD: u64 = 2 * 64;
+const GRID_MASK: u128 = (1 << consts::N_CELLS_NESTED) - 1;
+
+/// Uniformly random playouts (see [`SimulationState::simulate_random`]) of [`LANES`] independent
+/// games from the same state, advanced in lockstep.
+///
+/// All lanes start with the same player to move and every running lane makes one move per step,
+/// so the player to move is shared. Finding the available moves, applying the chosen ones and
+/// checking whether they won or filled their sub-board is done for all lanes at once with AVX2.
+/// Choosing a move and the rare updates after a decided sub-board are done per lane. Finished
+/// lanes keep their result and are skipped.
+///
+/// Every lane draws from its own [`SearchRng`], a lane plays exactly the game
+/// [`SimulationState::simulate_random`] would with the same generator.
+pub struct LaneSimulation {
+    /// cells occupied by each player per lane, won sub-boards are filled as in
+    /// [`SimulationState`]
+    occupied: [LaneBitsets; 2],
+    /// cells of the sub-board each lane is forced into, the whole grid if none is
+    forced: LaneBitsets,
+    super_boards: [[OneBitBoard; LANES]; 2],
+    drawn_boards: [OneBitBoard; LANES],
+    /// see [`SimulationState::simulate`], only changes when a board is won or drawn
+    line_completable: [bool; LANES],
+    active_player: Player,
+    initial_player: Player,
+    /// see [`SimulationState::simulate`], `None` while the lane is running
+    results: [Option<MonteCarloScore>; LANES],
+}
+
+impl LaneSimulation {
+    /// every lane starts at `start`, which must not be terminal
+    pub fn new(start: &SimulationState) -> Self {
+        let splat = |bits: u128| {
+            let [lo, hi] = split(bits);
+            [Aligned32([lo; LANES]), Aligned32([hi; LANES])]
+        };
+        let mut simulation = Self {
+            occupied: [
+                splat(start.occupied_by(Player::Player1)),
+                splat(start.occupied_by(Player::Player2)),
+            ],
+            forced: splat(forced_cells(start.forced_board())),
+            super_boards: [
+                [start.super_board(Player::Player1); LANES],
+                [start.super_board(Player::Player2); LANES],
+            ],
+            drawn_boards: [start.drawn_boards(); LANES],
+            line_completable: [true; LANES],
+            active_player: start.active_player(),
+            initial_player: start.active_player(),
+            results: [None; LANES],
+        };
+        if !simulation.any_line_completable(0) {
+            simulation.line_completable = [false; LANES];
+            let result = simulation.settled_score(0, simulation.lane_occupied(0));
+            simulation.results = [result; LANES];
+        }
+        simulation
+    }
+
+    /// plays every lane until its game ends, lane `i` draws its moves from `rngs[i]`
+    /// # Returns
+    /// the result of every lane, see [`SimulationState::simulate`]
+    pub fn simulate(mut self, rngs: &mut [SearchRng; LANES]) -> [MonteCarloScore; LANES] {
+        while self.results.iter().any(Option::is_none) {
+            self.step(rngs);
+        }
+        self.results
+            .map(|result| result.expect("every lane must have finished"))
+    }
+
+    /// one move in every running lane
+    fn step(&mut self, rngs: &mut [SearchRng; LANES]) {
+        let available = self.available_moves();
+        let player = self.active_player as usize;
+
+        // finished lanes make no move
+        let mut moves = empty_lanes();
+        let mut boards = Aligned32([NO_BOARD; LANES]);
+        let mut next_boards = Aligned32([NO_BOARD; LANES]);
+        for (lane, rng) in rngs.iter_mut().enumerate() {
+            if self.results[lane].is_some() {
+                continue;
+            }
+            let available = lane_bits(&available, lane);
+            debug_assert_ne!(available, 0, "a game without moves left is settled");
+
+            let move_ = simulation::random_move(available, rng);
+            set_lane_bits(&mut moves, lane, 1 << move_);
+            boards.0[lane] = board_shift(move_ / consts::N_CELLS as u8);
+            next_boards.0[lane] = board_shift(move_ % consts::N_CELLS as u8);
+        }
+        let running = moves_made(&boards);
+
+        let decided = self.place_moves(&moves, &boards, &next_boards);
+        for lane in (0..LANES).filter(|lane| running & (1 << lane) != 0) {
+            let board_idx = (boards.0[lane] / consts::N_CELLS as u64) as u8;
+            if decided.won & (1 << lane) != 0 {
+                let super_board = &mut self.super_boards[player][lane];
+                super_board.set_cell(board_idx);
+                if super_board.has_won() {
+                    self.results[lane] = Some(if self.active_player == self.initial_player {
+                        1
+                    } else {
+                        -1
+                    });
+                }
+            } else if decided.drawn & (1 << lane) != 0 {
+                self.drawn_boards[lane].set_cell(board_idx);
+            }
+
+            // like SimulationState::simulate, end once no super board line can be completed and
+            // the boards still winnable can not change the result
+            if (decided.won | decided.drawn) & (1 << lane) != 0 {
+                self.line_completable[lane] = self.any_line_completable(lane);
+            }
+            if !self.line_completable[lane] && self.results[lane].is_none() {
+                self.results[lane] = self.settled_score(lane, self.lane_occupied(lane));
+            }
+        }
+
+        self.active_player = self.active_player.other();
+    }
+
+    /// Plays the moves of the player to move and forces the lanes into the next sub-boards.
+    /// # Arguments
+    /// - `moves`: the bit of the move in every running lane, nothing in the others
+    /// - `boards`: the sub-board of each move, [`NO_BOARD`] for lanes without one
+    /// - `next_boards`: the sub-board the opponent is sent to by each move
+    fn place_moves(
+        &mut self,
+        moves: &LaneBitsets,
+        boards: &LaneShifts,
+        next_boards: &LaneShifts,
+    ) -> DecidedBoards {
+        let player = self.active_player as usize;
+        let [player1, player2] = &mut self.occupied;
+        let (own, opponent) = if player == 0 {
+            (player1, &*player2)
+        } else {
+            (player2, &*player1)
+        };
+        // safety: this may only be run on modern x86 cpus, main asserts feature is available
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            place_moves_x86_avx2(own, opponent, &mut self.forced, moves, boards, next_boards)
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        place_moves_fallback(own, opponent, &mut self.forced, moves, boards, next_boards)
+    }
+
+    fn lane_occupied(&self, lane: usize) -> [u128; 2] {
+        self.occupied
+            .each_ref()
+            .map(|bitsets| lane_bits(bitsets, lane))
+    }
+
+    fn any_line_completable(&self, lane: usize) -> bool {
+        simulation::any_line_completable(
+            self.super_boards.map(|boards| boards[lane].get()),
+            self.drawn_boards[lane].get(),
+        )
+    }
+
+    /// see [`simulation::settled_score`], the lane must not have a completable line
+    fn settled_score(&self, lane: usize, occupied: [u128; 2]) -> Option<MonteCarloScore> {
+        simulation::settled_score(
+            occupied,
+            self.super_boards.map(|boards| boards[lane].get()),
+            self.drawn_boards[lane].get(),
+            self.initial_player,
+        )
+    }
+
+    /// see [`SimulationState::available_in_board_or_fallback`], for every lane
+    fn available_moves(&self) -> LaneBitsets {
+        // safety: this may only be run on modern x86 cpus, main asserts feature is available
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            available_moves_x86_avx2(&self.occupied, &self.forced)
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        available_moves_fallback(&self.occupied, &self.forced)
+    }
+}
+
+const fn split(bits: u128) -> [u64; 2] {
+    [bits as u64, (bits >> 64) as u64]
+}
+
+const fn empty_lanes() -> LaneBitsets {
+    [Aligned32([0; LANES]), Aligned32([0; LANES])]
+}
+
+fn lane_bits(bitsets: &LaneBitsets, lane: usize) -> u128 {
+    bitsets[0].0[lane] as u128 | (bitsets[1].0[lane] as u128) << 64
+}
+
+fn set_lane_bits(bitsets: &mut LaneBitsets, lane: usize, bits: u128) {
+    [bitsets[0].0[lane], bitsets[1].0[lane]] = split(bits);
+}
+
+/// the sub-boards won or drawn by the moves of a step, one bit per lane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DecidedBoards {
+    won: u8,
+    drawn: u8,
+}
+
+/// index of the lowest cell of the sub-board in the grid
+const fn board_shift(board_idx: u8) -> u64 {
+    board_idx as u64 * consts::N_CELLS as u64
+}
+
+/// one bit per lane that has a board, see [`NO_BOARD`]
+fn moves_made(boards: &LaneShifts) -> u8 {
+    (0..LANES)
+        .filter(|lane| boards.0[*lane] != NO_BOARD)
+        .fold(0, |running, lane| running | 1 << lane)
+}
+
+/// the cells a move may be played in, before falling back to the whole grid for full boards
+const fn forced_cells(forced_board: u8) -> u128 {
+    if forced_board == NO_MOVE_FORCED {
+        GRID_MASK
+    } else {
+        BOARD_MASK << (forced_board as u32 * consts::N_CELLS)
+    }
+}
+
+/// safety: make sure this is run on x86-64 with avx2 enabled
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn available_moves_x86_avx2(
+    occupied: &[LaneBitsets; 2],
+    forced: &LaneBitsets,
+) -> LaneBitsets {
+    use core::arch::x86_64::{
+        _mm256_and_si256, _mm256_andnot_si256, _mm256_blendv_epi8, _mm256_cmpeq_epi64,
+        _mm256_load_si256, _mm256_or_si256, _mm256_set1_epi64x, _mm256_setzero_si256,
+        _mm256_store_si256,
+    };
+
+    let grid = split(GRID_MASK);
+    let mut free = [_mm256_setzero_si256(); 2];
+    let mut in_board = [_mm256_setzero_si256(); 2];
+    for half in 0..2 {
+        // safety: Aligned32 guarantees the alignment required by the aligned loads
+        let (player1, player2, forced) = unsafe {
+            (
+                _mm256_load_si256(occupied[0][half].0.as_ptr().cast()),
+                _mm256_load_si256(occupied[1][half].0.as_ptr().cast()),
+                _mm256_load_si256(forced[half].0.as_ptr().cast()),
+            )
+        };
+        free[half] = _mm256_andnot_si256(
+            _mm256_or_si256(player1, player2),
+            _mm256_set1_epi64x(grid[half] as i64),
+        );
+        in_board[half] = _mm256_and_si256(free[half], forced);
+    }
+    // all bits set in the lanes where the forced board is full
+    let board_full = _mm256_cmpeq_epi64(
+        _mm256_or_si256(in_board[0], in_board[1]),
+        _mm256_setzero_si256(),
+    );
+
+    let mut available = empty_lanes();
+    for half in 0..2 {
+        let bits = _mm256_blendv_epi8(in_board[half], free[half], board_full);
+        // safety: see the loads above
+        unsafe { _mm256_store_si256(available[half].0.as_mut_ptr().cast(), bits) };
+    }
+    available
+}
+
+#[allow(unused)]
+fn available_moves_fallback(occupied: &[LaneBitsets; 2], forced: &LaneBitsets) -> LaneBitsets {
+    let mut available = empty_lanes();
+    for lane in 0..LANES {
+        let free = !(lane_bits(&occupied[0], lane) | lane_bits(&occupied[1], lane)) & GRID_MASK;
+        let in_board = free & lane_bits(forced, lane);
+        set_lane_bits(
+            &mut available,
+            lane,
+            if in_board == 0 { free } else { in_board },
+        );
+    }
+    available
+}
+
+/// safety: make sure this is run on x86-64 with avx2 enabled
+///
+/// The sub-boards are shifted per lane with `srlv`/`sllv`, which give 0 for shifts of 64 or more
+/// (including the wrapped negative ones), so a sub-board may straddle both halves and lanes with
+/// [`NO_BOARD`] see an empty one.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn place_moves_x86_avx2(
+    own: &mut LaneBitsets,
+    opponent: &LaneBitsets,
+    forced: &mut LaneBitsets,
+    moves: &LaneBitsets,
+    boards: &LaneShifts,
+    next_boards: &LaneShifts,
+) -> DecidedBoards {
+    use core::arch::x86_64::{
+        __m256i, _mm256_and_si256, _mm256_andnot_si256, _mm256_blendv_epi8, _mm256_castsi256_pd,
+        _mm256_cmpeq_epi64, _mm256_load_si256, _mm256_movemask_pd, _mm256_or_si256,
+        _mm256_set1_epi64x, _mm256_setzero_si256, _mm256_sllv_epi64, _mm256_srlv_epi64,
+        _mm256_store_si256, _mm256_sub_epi64,
+    };
+
+    // safety: Aligned32 guarantees the alignment required by the aligned loads and stores
+    let load = |bits: &Aligned32<u64, LANES>| unsafe { _mm256_load_si256(bits.0.as_ptr().cast()) };
+    let store = |target: &mut Aligned32<u64, LANES>, bits: __m256i| unsafe {
+        _mm256_store_si256(target.0.as_mut_ptr().cast(), bits)
+    };
+    let sixty_four = _mm256_set1_epi64x(64);
+    let board_mask = _mm256_set1_epi64x(BOARD_MASK as i64);
+    // the 9 cells starting at the shift
+    let sub_board = |[lo, hi]: [__m256i; 2], shift: __m256i| {
+        let bits = _mm256_or_si256(
+            _mm256_or_si256(
+                _mm256_srlv_epi64(lo, shift),
+                _mm256_sllv_epi64(hi, _mm256_sub_epi64(sixty_four, shift)),
+            ),
+            _mm256_srlv_epi64(hi, _mm256_sub_epi64(shift, sixty_four)),
+        );
+        _mm256_and_si256(bits, board_mask)
+    };
+    // all cells of the sub-board at the shift
+    let board_cells = |shift: __m256i| {
+        [
+            _mm256_sllv_epi64(board_mask, shift),
+            _mm256_or_si256(
+                _mm256_sllv_epi64(board_mask, _mm256_sub_epi64(shift, sixty_four)),
+                _mm256_srlv_epi64(board_mask, _mm256_sub_epi64(sixty_four, shift)),
+            ),
+        ]
+    };
+
+    let moves = [load(&moves[0]), load(&moves[1])];
+    let own_after = [
+        _mm256_or_si256(load(&own[0]), moves[0]),
+        _mm256_or_si256(load(&own[1]), moves[1]),
+    ];
+    let all_after = [
+        _mm256_or_si256(own_after[0], load(&opponent[0])),
+        _mm256_or_si256(own_after[1], load(&opponent[1])),
+    ];
+    let boards = load(boards);
+
+    let own_board = sub_board(own_after, boards);
+    let won = consts::WINNER_MASKS_1BIT
+        .iter()
+        .fold(_mm256_setzero_si256(), |won, mask| {
+            let mask = _mm256_set1_epi64x(*mask as i64);
+            _mm256_or_si256(
+                won,
+                _mm256_cmpeq_epi64(_mm256_and_si256(own_board, mask), mask),
+            )
+        });
+    let full = _mm256_cmpeq_epi64(sub_board(all_after, boards), board_mask);
+    let drawn = _mm256_andnot_si256(won, full);
+
+    // block all cells in a won board, like SimulationState::apply_move
+    let won_cells = board_cells(boards);
+    for half in 0..2 {
+        let placed = _mm256_blendv_epi8(moves[half], won_cells[half], won);
+        store(&mut own[half], _mm256_or_si256(own_after[half], placed));
+    }
+    let next_cells = board_cells(load(next_boards));
+    store(&mut forced[0], next_cells[0]);
+    store(&mut forced[1], next_cells[1]);
+
+    DecidedBoards {
+        won: _mm256_movemask_pd(_mm256_castsi256_pd(won)) as u8,
+        drawn: _mm256_movemask_pd(_mm256_castsi256_pd(drawn)) as u8,
+    }
+}
+
+#[allow(unused)]
+fn place_moves_fallback(
+    own: &mut LaneBitsets,
+    opponent: &LaneBitsets,
+    forced: &mut LaneBitsets,
+    moves: &LaneBitsets,
+    boards: &LaneShifts,
+    next_boards: &LaneShifts,
+) -> DecidedBoards {
+    let mut decided = DecidedBoards { won: 0, drawn: 0 };
+    for lane in 0..LANES {
+        if boards.0[lane] == NO_BOARD {
+            set_lane_bits(forced, lane, 0);
+            continue;
+        }
+        let shift = boards.0[lane] as u32;
+        let own_after = lane_bits(own, lane) | lane_bits(moves, lane);
+        let all_after = own_after | lane_bits(opponent, lane);
+        let board_won = OneBitBoard::new((own_after >> shift) as BoardState).has_won();
+        let board_full = OneBitBoard::new((all_after >> shift) as BoardState).is_full();
+        if board_won {
+            decided.won |= 1 << lane;
+            set_lane_bits(own, lane, own_after | BOARD_MASK << shift);
+        } else {
+            decided.drawn |= (board_full as u8) << lane;
+            set_lane_bits(own, lane, own_after);
+        }
+        set_lane_bits(forced, lane, BOARD_MASK << next_boards.0[lane]);
+    }
+    decided
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        rng::{self, SearchRng},
+        tree::{
+            lane_simulation::{LANES, LaneSimulation},
+            node_state::NodeState,
+            simulation::SimulationState,
+        },
+    };
+
+    fn simulation_after(moves: &[u8]) -> SimulationState {
+        moves
+            .iter()
+            .fold(NodeState::empty(), |state, move_| {
+                state.apply_move(*move_).0
+            })
+            .into_simulation()
+    }
+
+    fn lane_rngs(seed: u64, batch: u64) -> [SearchRng; LANES] {
+        std::array::from_fn(|lane| {
+            SearchRng::from_seed(rng::stream_seed(seed, batch * LANES as u64 + lane as u64))
+        })
+    }
+
+    #[test]
+    fn lanes_replay_scalar_playouts() {
+        for state in [SimulationState::empty(), simulation_after(&[40, 36, 4])] {
+            for batch in 0..50 {
+                let mut rngs = lane_rngs(23, batch);
+                let mut scalar_rngs = rngs.clone();
+                let lane_results = LaneSimulation::new(&state).simulate(&mut rngs);
+                let scalar_results = scalar_rngs.each_mut().map(|rng| state.simulate_random(rng));
+                assert_eq!(lane_results, scalar_results);
+            }
+        }
+    }
+
+    #[test]
+    fn outcome_distribution_matches_simulate_random() {
+        const BATCHES: u64 = 1_000;
+        const PLAYOUTS: usize = BATCHES as usize * LANES;
+        let state = simulation_after(&[40]);
+
+        // losses, draws and wins for the player to move
+        let mut lane_outcomes = [0; 3];
+        for batch in 0..BATCHES {
+            for result in LaneSimulation::new(&state).simulate(&mut lane_rngs(7, batch)) {
+                lane_outcomes[(result + 1) as usize] += 1;
+            }
+        }
+        let mut scalar_outcomes = [0; 3];
+        let mut rng = SearchRng::from_seed(8);
+        for _ in 0..PLAYOUTS {
+            scalar_outcomes[(state.simulate_random(&mut rng) + 1) as usize] += 1;
+        }
+
+        for (lane, scalar) in lane_outcomes.into_iter().zip(scalar_outcomes) {
+            let difference = (lane as f32 - scalar as f32).abs() / PLAYOUTS as f32;
+            assert!(
+                difference < 0.04,
+                "outcomes differ: lanes {lane_outcomes:?}, scalar {scalar_outcomes:?}"
+            );
+        }
+        assert!(lane_outcomes.iter().all(|&count| count > 0));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_matches_fallback() {
+        use crate::tree::lane_simulation::{
+            NO_BOARD, available_moves_fallback, available_moves_x86_avx2, board_shift, lane_bits,
+            place_moves_fallback, place_moves_x86_avx2, set_lane_bits,
+        };
+        use crate::{consts, tree::simulation, types::Aligned32};
+
+        let mut n_decided = 0;
+        for start in [simulation_after(&[40]), simulation_after(&[0, 8, 72])] {
+            let mut simulation = LaneSimulation::new(&start);
+            let mut rngs = lane_rngs(3, 0);
+            let mut move_rng = SearchRng::from_seed(4);
+            while simulation.results.iter().any(Option::is_none) {
+                let avx2 =
+                    unsafe { available_moves_x86_avx2(&simulation.occupied, &simulation.forced) };
+                let fallback = available_moves_fallback(&simulation.occupied, &simulation.forced);
+                assert_eq!(avx2, fallback);
+
+                // any move in the running lanes, also ones which win a sub-board
+                let (mut moves, mut boards, mut next_boards) = (
+                    [Aligned32([0; LANES]), Aligned32([0; LANES])],
+                    Aligned32([NO_BOARD; LANES]),
+                    Aligned32([NO_BOARD; LANES]),
+                );
+                for lane in (0..LANES).filter(|lane| simulation.results[*lane].is_none()) {
+                    let move_ = simulation::random_move(lane_bits(&avx2, lane), &mut move_rng);
+                    set_lane_bits(&mut moves, lane, 1 << move_);
+                    boards.0[lane] = board_shift(move_ / consts::N_CELLS as u8);
+                    next_boards.0[lane] = board_shift(move_ % consts::N_CELLS as u8);
+                }
+                let [player1, player2] = simulation.occupied;
+                let (mut avx2_own, mut avx2_forced) = (player1, simulation.forced);
+                let (mut fallback_own, mut fallback_forced) = (player1, simulation.forced);
+                let avx2_decided = unsafe {
+                    place_moves_x86_avx2(
+                        &mut avx2_own,
+                        &player2,
+                        &mut avx2_forced,
+                        &moves,
+                        &boards,
+                        &next_boards,
+                    )
+                };
+                let fallback_decided = place_moves_fallback(
+                    &mut fallback_own,
+                    &player2,
+                    &mut fallback_forced,
+                    &moves,
+                    &boards,
+                    &next_boards,
+                );
+                assert_eq!(avx2_decided, fallback_decided);
+                n_decided += (avx2_decided.won | avx2_decided.drawn).count_ones();
+                assert_eq!(avx2_own, fallback_own);
+                assert_eq!(avx2_forced, fallback_forced);
+
+                simulation.step(&mut rngs);
+            }
+        }
+        assert!(n_decided != 0);
+    }
+}
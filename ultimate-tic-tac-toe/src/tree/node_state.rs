@@ -2,11 +2,56 @@ use crate::{
     bitmagic,
     board::one_bit::OneBitBoard,
     consts,
-    tree::{MonteCarloScore, NO_MOVE_FORCED, simulation::SimulationState},
+    tree::{
+        MonteCarloScore, NO_MOVE_FORCED,
+        simulation::{self, SimulationState},
+    },
     types::{BoardState, Player},
     util::BoardMajorBitset,
 };
 
+/// splitmix64, only used to generate the zobrist keys at compile time
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b9_7f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d_1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb_133111eb);
+    z ^ (z >> 31)
+}
+
+/// random keys xor-ed together for every property of a [`NodeState`]
+struct ZobristKeys {
+    cells: [[u64; consts::N_CELLS_NESTED as usize]; 2],
+    /// index [`NO_MOVE_FORCED`] included
+    forced_board: [u64; consts::N_BOARDS as usize + 1],
+    player2_to_move: u64,
+}
+
+const ZOBRIST: ZobristKeys = {
+    let mut seed = 0xc0de_5eed_2b15_7e11;
+    let mut cells = [[0; consts::N_CELLS_NESTED as usize]; 2];
+    let mut player = 0;
+    while player < 2 {
+        let mut cell = 0;
+        while cell < consts::N_CELLS_NESTED as usize {
+            cells[player][cell] = splitmix64(&mut seed);
+            cell += 1;
+        }
+        player += 1;
+    }
+    let mut forced_board = [0; consts::N_BOARDS as usize + 1];
+    let mut board = 0;
+    while board < forced_board.len() {
+        forced_board[board] = splitmix64(&mut seed);
+        board += 1;
+    }
+    ZobristKeys {
+        cells,
+        forced_board,
+        player2_to_move: splitmix64(&mut seed),
+    }
+};
+
 /// TODO MERBUG: is it possible to reach the same state but with a different active player?
 /// NOTE: NodeState::default() is not a valid node state and more of a placeholder
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
@@ -14,6 +59,7 @@ pub(super) struct NodeState {
     /// # bits[0]
     /// bitset indicating is_occupied for player 1
     /// upper 32 bits are reserved for meta data
+    /// [104:96] = sub-boards that are full without a winner
     /// [127:119] = "super board" / board containing is_won for subboards for player 1
     ///
     /// # bits[1] (including meta)
@@ -24,18 +70,70 @@ pub(super) struct NodeState {
     /// [112:112] = active player as u8, see [`Player`]
     /// [127:119] = "super board" / board containing is_won for subboards for player 2
     bits: [u128; 2],
+    /// zobrist hash of the occupied cells, forced board and active player, updated
+    /// incrementally by [`Self::apply_move`] (the won and drawn boards follow from the occupied
+    /// cells)
+    zobrist: u64,
 }
 
 impl NodeState {
     const META_OFFSET: u8 = (128 - 32);
     const PLAYER_OFFSET_IN_META: u8 = 16;
     const SUPER_BOARD_OFFSET_IN_META: u8 = 32 - consts::N_BOARDS as u8;
+    /// in the meta bits of player 1
+    const DRAWN_BOARDS_OFFSET_IN_META: u8 = 0;
+    const ALL_BOARDS: BoardState = 0b1_1111_1111;
     //                        player -|   forced_board -|:|
     const META_BITS_TO_CLEAR: u32 = 0b1_1111_1111_1111_1111;
     pub(super) const fn empty() -> Self {
         Self {
             bits: [0, (NO_MOVE_FORCED as u128) << Self::META_OFFSET],
+            zobrist: ZOBRIST.forced_board[NO_MOVE_FORCED as usize],
+        }
+    }
+
+    /// the position after playing `moves` (col-major board moves) from the empty board
+    /// # Panics
+    /// if a move is not available or the game is over after the moves
+    pub(super) fn after_moves(moves: &[u8]) -> Self {
+        let mut state = Self::empty();
+        for &move_ in moves {
+            assert!(
+                state.available_in_board_or_fallback().get() & (1 << move_) != 0,
+                "move {move_} is not available"
+            );
+            let has_won;
+            (state, has_won) = state.apply_move(move_);
+            assert!(!has_won, "the game is over after move {move_}");
         }
+        assert!(!state.is_settled(), "the game is over after the moves");
+        state
+    }
+
+    pub(super) const fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// xor of the keys of the cells in `newly_occupied`
+    const fn zobrist_cells(player: Player, mut newly_occupied: u128) -> u64 {
+        let mut zobrist = 0;
+        while newly_occupied != 0 {
+            let cell = newly_occupied.trailing_zeros() as usize;
+            zobrist ^= ZOBRIST.cells[player as usize][cell];
+            newly_occupied &= newly_occupied - 1;
+        }
+        zobrist
+    }
+
+    /// the zobrist hash computed from scratch
+    const fn compute_zobrist(&self) -> u64 {
+        let mut zobrist = Self::zobrist_cells(Player::Player1, self.player1_occupied().get())
+            ^ Self::zobrist_cells(Player::Player2, self.player2_occupied().get())
+            ^ ZOBRIST.forced_board[self.forced_board() as usize];
+        if let Player::Player2 = self.active_player() {
+            zobrist ^= ZOBRIST.player2_to_move;
+        }
+        zobrist
     }
     pub(super) const fn player1_occupied(&self) -> BoardMajorBitset {
         BoardMajorBitset::new_truncated(self.bits[0])
@@ -62,6 +160,59 @@ impl NodeState {
         self.meta_player(player) >> Self::SUPER_BOARD_OFFSET_IN_META
     }
 
+    /// the sub-boards that were filled without a winner
+    pub(super) const fn drawn_boards(&self) -> BoardState {
+        (self.meta_player(Player::Player1) >> Self::DRAWN_BOARDS_OFFSET_IN_META) & Self::ALL_BOARDS
+    }
+
+    /// # Returns
+    /// bitset over [`consts::WINNER_MASKS_1BIT`] of the super board lines the player can still
+    /// complete, i.e. lines without a board won by the opponent or drawn
+    pub(super) fn completable_lines(&self, player: Player) -> u8 {
+        OneBitBoard::new(self.super_board_for_player(player.other()) | self.drawn_boards())
+            .free_lines()
+    }
+
+    /// the result once no super board line can be completed and the open boards can not change
+    /// it anymore, see [`simulation::settled_score`]
+    pub(super) fn settled_score(&self, in_favor_of: Player) -> Option<MonteCarloScore> {
+        if self.completable_lines(Player::Player1) | self.completable_lines(Player::Player2) != 0 {
+            return None;
+        }
+        simulation::settled_score(
+            [self.player1_occupied().get(), self.player2_occupied().get()],
+            [Player::Player1, Player::Player2].map(|player| self.super_board_for_player(player)),
+            self.drawn_boards(),
+            in_favor_of,
+        )
+    }
+
+    /// the game is over without a winning line, see [`Self::settled_score`]
+    pub(super) fn is_settled(&self) -> bool {
+        self.settled_score(self.active_player()).is_some()
+    }
+
+    /// the fixed result of a game that is won or settled, in favor of the player to move
+    pub(super) fn terminal_score(&self) -> MonteCarloScore {
+        let player_to_move = self.active_player();
+        if self.has_won(player_to_move.other()) {
+            -1
+        } else {
+            self.settled_score(player_to_move)
+                .expect("the game must be won or settled")
+        }
+    }
+
+    /// every sub-board is won or drawn, so there are no moves left
+    pub(super) fn all_boards_decided(&self) -> bool {
+        OneBitBoard::new(
+            self.super_board_for_player(Player::Player1)
+                | self.super_board_for_player(Player::Player2)
+                | self.drawn_boards(),
+        )
+        .is_full()
+    }
+
     pub(super) const fn get_player_board(&self, player: Player, board_idx: u8) -> OneBitBoard {
         OneBitBoard::new(
             (self.bits[player as usize] >> (board_idx * consts::N_CELLS as u8)) as BoardState,
@@ -89,6 +240,11 @@ impl NodeState {
         }
     }
 
+    /// cells no player occupies, the cells of won boards count as occupied
+    pub(super) fn n_empty_cells(&self) -> u32 {
+        bitmagic::count_ones_u128((!(self.player1_occupied() | self.player2_occupied())).get())
+    }
+
     pub(crate) fn decide_draw(&self, in_favor_of: Player) -> MonteCarloScore {
         let won_board_favored_player =
             bitmagic::count_ones_u32(self.super_board_for_player(in_favor_of));
@@ -102,7 +258,7 @@ impl NodeState {
         }
     }
 
-    fn has_won(&self, player: Player) -> bool {
+    pub(super) fn has_won(&self, player: Player) -> bool {
         OneBitBoard::new(self.super_board_for_player(player)).has_won()
     }
 
@@ -116,12 +272,13 @@ impl NodeState {
         let player = self.active_player();
 
         let board_idx = board_col_major_idx / consts::N_CELLS as u8;
+        let forced_board = board_col_major_idx % consts::N_CELLS as u8;
 
         child_state.bits[player as usize] |= 0b1 << board_col_major_idx;
 
         let has_won_subboard = child_state.get_player_board(player, board_idx).has_won();
-        let new_general_meta: u32 = ((player.other() as u32) << Self::PLAYER_OFFSET_IN_META)
-            | (board_col_major_idx % consts::N_CELLS as u8) as u32;
+        let new_general_meta: u32 =
+            ((player.other() as u32) << Self::PLAYER_OFFSET_IN_META) | forced_board as u32;
 
         if has_won_subboard {
             // block all cells in that board (simpler logic for available moves)
@@ -130,6 +287,18 @@ impl NodeState {
             // track wins in super board (specific to each player, not in general meta)
             child_state.bits[player as usize] |=
                 1 << (Self::META_OFFSET + Self::SUPER_BOARD_OFFSET_IN_META + board_idx);
+        } else if OneBitBoard::new(
+            child_state
+                .get_player_board(Player::Player1, board_idx)
+                .get()
+                | child_state
+                    .get_player_board(Player::Player2, board_idx)
+                    .get(),
+        )
+        .is_full()
+        {
+            child_state.bits[Player::Player1 as usize] |=
+                1 << (Self::META_OFFSET + Self::DRAWN_BOARDS_OFFSET_IN_META + board_idx);
         }
 
         // clear meta bits before setting
@@ -137,6 +306,15 @@ impl NodeState {
             !((Self::META_BITS_TO_CLEAR as u128) << Self::META_OFFSET);
         child_state.bits[Player::Player2 as usize] |=
             (new_general_meta as u128) << Self::META_OFFSET;
+
+        // usually only the move itself, all remaining cells of the board if it was won
+        let newly_occupied = (child_state.bits[player as usize] & !self.bits[player as usize])
+            & BoardMajorBitset::new_truncated(u128::MAX).get();
+        child_state.zobrist ^= Self::zobrist_cells(player, newly_occupied)
+            ^ ZOBRIST.forced_board[self.forced_board() as usize]
+            ^ ZOBRIST.forced_board[forced_board as usize]
+            ^ ZOBRIST.player2_to_move;
+        debug_assert_eq!(child_state.zobrist, child_state.compute_zobrist());
         let won_game = if has_won_subboard {
             child_state.has_won(player)
         } else {
@@ -146,11 +324,33 @@ impl NodeState {
         (child_state, won_game)
     }
 
+    /// the raw bits split into words (and the zobrist hash), e.g. for storing them in atomics
+    pub(super) const fn to_words(self) -> [u64; 5] {
+        [
+            self.bits[0] as u64,
+            (self.bits[0] >> 64) as u64,
+            self.bits[1] as u64,
+            (self.bits[1] >> 64) as u64,
+            self.zobrist,
+        ]
+    }
+    /// inverse of [`Self::to_words`]
+    pub(super) const fn from_words(words: [u64; 5]) -> Self {
+        Self {
+            bits: [
+                words[0] as u128 | (words[1] as u128) << 64,
+                words[2] as u128 | (words[3] as u128) << 64,
+            ],
+            zobrist: words[4],
+        }
+    }
+
     pub(super) fn into_simulation(self) -> SimulationState {
         SimulationState::new(
             self.bits.map(BoardMajorBitset::new_truncated),
             [Player::Player1, Player::Player2]
                 .map(|player| OneBitBoard::new(self.super_board_for_player(player))),
+            OneBitBoard::new(self.drawn_boards()),
             self.active_player(),
             self.forced_board(),
         )
@@ -159,7 +359,7 @@ impl NodeState {
 
 #[cfg(test)]
 mod test {
-    use crate::{consts, tree::NodeState};
+    use crate::{consts, tree::NodeState, types::Player};
 
     #[test]
     fn test_apply_move() {
@@ -217,4 +417,77 @@ mod test {
             0b1_1111_1111 << (state.forced_board() * consts::N_CELLS as u8)
         );
     }
+
+    #[test]
+    fn zobrist_is_updated_incrementally() {
+        // player 1 wins board 0, which fills its remaining cells
+        let moves = [0, 9, 1, 10, 2];
+        let mut state = NodeState::empty();
+        assert_eq!(state.zobrist(), state.compute_zobrist());
+        for move_ in moves {
+            state = state.apply_move(move_).0;
+            assert_eq!(state.zobrist(), state.compute_zobrist());
+        }
+        assert_eq!(NodeState::from_words(state.to_words()), state);
+
+        // the same position reached through a different move order
+        let transposed = [1, 10, 0, 9, 2]
+            .iter()
+            .fold(NodeState::empty(), |state, move_| {
+                state.apply_move(*move_).0
+            });
+        assert_eq!(transposed, state);
+        assert_eq!(transposed.zobrist(), state.zobrist());
+
+        let other = state.apply_move(40).0;
+        assert_ne!(other.zobrist(), state.zobrist());
+    }
+
+    #[test]
+    fn tracks_drawn_boards() {
+        // fills board 0 without a winner (again disregarding the rules):
+        // x o x
+        // x o o
+        // o x x
+        let moves = [0, 3, 6, 4, 1, 7, 5, 2];
+        let state = moves.iter().fold(NodeState::empty(), |state, move_| {
+            state.apply_move(*move_).0
+        });
+        assert_eq!(state.drawn_boards(), 0);
+        assert_eq!(state.completable_lines(Player::Player1), 0b1111_1111);
+
+        let (state, won) = state.apply_move(8);
+        assert!(!won);
+        assert_eq!(state.drawn_boards(), 0b1);
+        assert_eq!(state.super_board_for_player(Player::Player1), 0);
+        assert_eq!(state.player1_occupied().get(), 0b1_0110_0011);
+        assert_eq!(NodeState::from_words(state.to_words()), state);
+        assert_eq!(
+            state.into_simulation().drawn_boards().get(),
+            state.drawn_boards()
+        );
+
+        // the column, the row and the diagonal through board 0 are dead for both players
+        for player in [Player::Player1, Player::Player2] {
+            let completable_lines = state.completable_lines(player);
+            assert_eq!(completable_lines.count_ones(), 5);
+            assert!(
+                consts::WINNER_MASKS_1BIT
+                    .iter()
+                    .enumerate()
+                    .all(|(line_idx, mask)| (completable_lines >> line_idx & 1 != 0)
+                        == (mask & 0b1 == 0))
+            );
+        }
+        assert!(!state.all_boards_decided());
+
+        // player 2 winning board 1 also removes the row through it for player 1
+        let state = [9, 12, 10, 13, 11]
+            .iter()
+            .fold(state, |state, move_| state.apply_move(*move_).0);
+        assert_eq!(state.super_board_for_player(Player::Player2), 0b10);
+        assert_eq!(state.drawn_boards(), 0b1);
+        assert_eq!(state.completable_lines(Player::Player1).count_ones(), 4);
+        assert_eq!(state.completable_lines(Player::Player2).count_ones(), 5);
+    }
 }
@@ -0,0 +1,340 @@
+use std::time::Instant;
+
+use crate::{
+    consts,
+    tree::{NodeIdx, node_state::NodeState},
+    types::Player,
+};
+
+/// proof and disproof numbers, [`INFINITE`] once the other one is 0
+type ProofNumber = u32;
+const INFINITE: ProofNumber = ProofNumber::MAX;
+
+/// outcome of [`ProofNumberSearch::prove`], relative to the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ProofNumberResult {
+    /// the player to move can force a win, e.g. by playing the move
+    Win { move_: u8 },
+    /// the opponent can force a win no matter what the player to move does
+    Loss,
+    /// the node budget (or time) ran out, or neither player can force a win
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PnNode {
+    state: NodeState,
+    proof: ProofNumber,
+    disproof: ProofNumber,
+    parent: NodeIdx,
+    /// children are stored consecutively, 0 while the node is not expanded
+    first_child: NodeIdx,
+    child_count: u8,
+    /// the move leading from the parent to this node
+    move_: u8,
+}
+
+impl PnNode {
+    const fn new(state: NodeState, parent: NodeIdx, move_: u8) -> Self {
+        Self {
+            state,
+            proof: 1,
+            disproof: 1,
+            parent,
+            first_child: 0,
+            child_count: 0,
+            move_,
+        }
+    }
+}
+
+/// Proof-number search (Allis) answering whether a player can force a win.
+///
+/// The search tree is kept in a flat arena which is reused between calls, transpositions are
+/// searched separately. Games that end without a winner are decided by
+/// [`NodeState::decide_draw`] like everywhere else in the tree, settled ones (see
+/// [`NodeState::settled_score`]) are not searched any further.
+#[derive(Debug, Clone)]
+pub(super) struct ProofNumberSearch {
+    /// nodes a single [`Self::prove`] may create, 0 disables the search
+    node_budget: usize,
+    nodes: Vec<PnNode>,
+}
+
+impl ProofNumberSearch {
+    /// how many expansions happen between two looks at the clock
+    const EXPANSIONS_PER_TIME_CHECK: usize = 16;
+
+    pub(super) fn new(node_budget: usize) -> Self {
+        Self {
+            node_budget,
+            // the last expansion may exceed the budget, the arena never has to grow
+            nodes: Vec::with_capacity(node_budget + consts::N_CELLS_NESTED as usize + 1),
+        }
+    }
+
+    /// approximate number of bytes occupied by the arena
+    pub(super) fn memory_used(&self) -> usize {
+        self.nodes.capacity() * size_of::<PnNode>()
+    }
+
+    /// Tries to prove a win for the player to move, if that fails a win for the opponent.
+    /// The game must not be over already.
+    ///
+    /// # Arguments
+    /// - `deadline`: gives up once reached, on top of the node budget
+    pub(super) fn prove(
+        &mut self,
+        state: &NodeState,
+        deadline: Option<Instant>,
+    ) -> ProofNumberResult {
+        let player_to_move = state.active_player();
+        let mut node_budget = self.node_budget;
+
+        match self.prove_win(state, player_to_move, &mut node_budget, deadline) {
+            Some(true) => {
+                let winning_child = self.children(0).find(|child| child.proof == 0);
+                return ProofNumberResult::Win {
+                    move_: winning_child
+                        .expect("a proven OR node has a proven child")
+                        .move_,
+                };
+            }
+            Some(false) => {}
+            None => return ProofNumberResult::Unknown,
+        }
+        match self.prove_win(state, player_to_move.other(), &mut node_budget, deadline) {
+            Some(true) => ProofNumberResult::Loss,
+            Some(false) | None => ProofNumberResult::Unknown,
+        }
+    }
+
+    fn children(&self, node_idx: NodeIdx) -> impl Iterator<Item = &PnNode> {
+        let node = &self.nodes[node_idx as usize];
+        self.nodes[node.first_child as usize..][..node.child_count as usize].iter()
+    }
+
+    /// # Returns
+    /// - Some(true) if `attacker` can force a win
+    /// - Some(false) if they can not
+    /// - None if the budget or time ran out first
+    fn prove_win(
+        &mut self,
+        state: &NodeState,
+        attacker: Player,
+        node_budget: &mut usize,
+        deadline: Option<Instant>,
+    ) -> Option<bool> {
+        self.nodes.clear();
+        self.nodes.push(PnNode::new(*state, 0, 0));
+
+        let mut expansions = 0;
+        loop {
+            let root = &self.nodes[0];
+            if root.proof == 0 {
+                return Some(true);
+            }
+            if root.disproof == 0 {
+                return Some(false);
+            }
+            if *node_budget == 0 {
+                return None;
+            }
+            expansions += 1;
+            if expansions % Self::EXPANSIONS_PER_TIME_CHECK == 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return None;
+            }
+
+            let most_proving = self.most_proving_node(attacker);
+            let n_children = self.expand(most_proving, attacker);
+            *node_budget = node_budget.saturating_sub(n_children);
+            self.update_ancestors(most_proving, attacker);
+        }
+    }
+
+    /// descends from the root along the children which determine the proof numbers: the cheapest
+    /// to prove below OR nodes (attacker to move), the cheapest to disprove below AND nodes
+    fn most_proving_node(&self, attacker: Player) -> NodeIdx {
+        let mut node_idx = 0;
+        loop {
+            let node = &self.nodes[node_idx as usize];
+            if node.child_count == 0 {
+                return node_idx;
+            }
+            let is_or_node = node.state.active_player() == attacker;
+            node_idx = (node.first_child..node.first_child + node.child_count as NodeIdx)
+                .find(|child_idx| {
+                    let child = &self.nodes[*child_idx as usize];
+                    if is_or_node {
+                        child.proof == node.proof
+                    } else {
+                        child.disproof == node.disproof
+                    }
+                })
+                .expect("the proof numbers of a node are determined by one of its children");
+        }
+    }
+
+    /// adds all children of the leaf and evaluates the ones that end or settle the game
+    /// # Returns
+    /// the number of added children
+    fn expand(&mut self, node_idx: NodeIdx, attacker: Player) -> usize {
+        let state = self.nodes[node_idx as usize].state;
+        let first_child = self.nodes.len() as NodeIdx;
+        let mut available = state.available_in_board_or_fallback().get();
+        debug_assert_ne!(available, 0, "terminal nodes are never expanded");
+
+        while available != 0 {
+            let move_ = available.trailing_zeros() as u8;
+            available &= available - 1;
+
+            let (child_state, has_won) = state.apply_move(move_);
+            let mut child = PnNode::new(child_state, node_idx, move_);
+            let attacker_won = if has_won {
+                Some(state.active_player() == attacker)
+            } else if child_state.all_boards_decided() {
+                Some(child_state.decide_draw(attacker) > 0)
+            } else {
+                // the open boards can not change the result anymore
+                child_state
+                    .settled_score(attacker)
+                    .map(|attacker_score| attacker_score > 0)
+            };
+            match attacker_won {
+                Some(true) => (child.proof, child.disproof) = (0, INFINITE),
+                Some(false) => (child.proof, child.disproof) = (INFINITE, 0),
+                None => {}
+            }
+            self.nodes.push(child);
+        }
+
+        let child_count = self.nodes.len() - first_child as usize;
+        let node = &mut self.nodes[node_idx as usize];
+        node.first_child = first_child;
+        node.child_count = child_count as u8;
+        child_count
+    }
+
+    /// recomputes the proof numbers from the node up to the root
+    fn update_ancestors(&mut self, mut node_idx: NodeIdx, attacker: Player) {
+        loop {
+            let node = &self.nodes[node_idx as usize];
+            let is_or_node = node.state.active_player() == attacker;
+            let (mut min_proof, mut sum_proof) = (INFINITE, 0 as ProofNumber);
+            let (mut min_disproof, mut sum_disproof) = (INFINITE, 0 as ProofNumber);
+            for child in self.children(node_idx) {
+                min_proof = min_proof.min(child.proof);
+                sum_proof = sum_proof.saturating_add(child.proof);
+                min_disproof = min_disproof.min(child.disproof);
+                sum_disproof = sum_disproof.saturating_add(child.disproof);
+            }
+
+            let node = &mut self.nodes[node_idx as usize];
+            (node.proof, node.disproof) = if is_or_node {
+                (min_proof, sum_disproof)
+            } else {
+                (sum_proof, min_disproof)
+            };
+            if node_idx == 0 {
+                return;
+            }
+            node_idx = node.parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        rng::SearchRng,
+        tree::{
+            endgame::{self, EndgameSolver},
+            node_state::NodeState,
+            proof_number::{ProofNumberResult, ProofNumberSearch},
+        },
+    };
+
+    fn state_after(moves: &[u8]) -> NodeState {
+        moves.iter().fold(NodeState::empty(), |state, move_| {
+            state.apply_move(*move_).0
+        })
+    }
+
+    #[test]
+    fn finds_immediate_win() {
+        // player 1 to move, has won boards 0 and 1 and wins board 2 (and the game) with 20
+        let state = state_after(&[0, 36, 1, 49, 2, 62, 9, 73, 10, 66, 11, 28, 18, 57, 19, 74]);
+        let mut search = ProofNumberSearch::new(10_000);
+        assert_eq!(
+            search.prove(&state, None),
+            ProofNumberResult::Win { move_: 20 }
+        );
+    }
+
+    #[test]
+    fn agrees_with_the_endgame_solver() {
+        let mut rng = SearchRng::from_seed(15);
+        let mut search = ProofNumberSearch::new(1_000_000);
+        let mut solver = EndgameSolver::new(10);
+        let mut n_proven = 0;
+        for state in (0..500)
+            .filter_map(|_| endgame::test::random_endgame(10, &mut rng))
+            .take(50)
+        {
+            let score = solver.solve(&state);
+            match search.prove(&state, None) {
+                ProofNumberResult::Win { move_ } => {
+                    assert_eq!(score, 1);
+                    let (child_state, has_won) = state.apply_move(move_);
+                    assert!(has_won || solver.solve(&child_state) == -1);
+                    n_proven += 1;
+                }
+                ProofNumberResult::Loss => {
+                    assert_eq!(score, -1);
+                    n_proven += 1;
+                }
+                // draws can not be proven as a win for either player
+                ProofNumberResult::Unknown => assert_eq!(score, 0),
+            }
+        }
+        assert!(n_proven != 0);
+    }
+
+    #[test]
+    fn settled_children_are_proven() {
+        // a move after which the player who made it has won more boards than the opponent can
+        let mut rng = SearchRng::from_seed(15);
+        let (state, settling_move) =
+            std::iter::repeat_with(|| endgame::test::random_settling_move(&mut rng))
+                .find(|(state, move_)| {
+                    let child_state = state.apply_move(*move_).0;
+                    child_state.settled_score(state.active_player()) == Some(1)
+                })
+                .unwrap();
+
+        let mut search = ProofNumberSearch::new(10_000);
+        assert_eq!(
+            search.prove(&state, None),
+            ProofNumberResult::Win {
+                move_: settling_move
+            }
+        );
+        // proven by expanding the root only
+        assert_eq!(
+            search.nodes.len(),
+            1 + state.available_in_board_or_fallback().get().count_ones() as usize
+        );
+    }
+
+    #[test]
+    fn gives_up_when_out_of_budget() {
+        let mut search = ProofNumberSearch::new(1_000);
+        assert_eq!(
+            search.prove(&NodeState::empty(), None),
+            ProofNumberResult::Unknown
+        );
+        assert!(search.nodes.len() <= 1_000 + 81);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{num::NonZeroUsize, thread, time::Instant};
+
+use crate::{
+    config::SearchConfig,
+    consts, rng,
+    tree::{
+        Tree,
+        selection::{ChildStats, FinalSelection, UCBScore},
+    },
+};
+
+/// Root parallelisation: every thread grows its own independent [`Tree`] from the same
+/// root with its own RNG stream, the root statistics are merged when choosing a move.
+pub struct RootParallelTree {
+    trees: Vec<Tree>,
+    /// see [`SearchConfig::final_selection`]
+    final_selection: FinalSelection,
+    seed: u64,
+    /// used to derive fresh RNG streams for every search
+    n_searches: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MergedMoveStats {
+    pub visits: u64,
+    /// sum of the mean values weighted by their visits, in favor of the player to move at the
+    /// root
+    pub score: f64,
+    /// sum of the mean squared values weighted by their visits
+    pub score_squared: f64,
+    /// 1 if a tree proved the move to win, -1 if to lose and 0 otherwise (also for a proven
+    /// draw), in favor of the player to move at the root
+    pub proven: i8,
+}
+
+impl RootParallelTree {
+    /// trees for the empty board, player 1 to move
+    pub fn new(config: &SearchConfig, n_threads: NonZeroUsize) -> Self {
+        Self::from_moves(config, n_threads, &[])
+    }
+    /// trees for the position after `moves`, see [`Tree::from_moves`], they share the
+    /// [`SearchConfig::memory_budget`] evenly
+    pub fn from_moves(config: &SearchConfig, n_threads: NonZeroUsize, moves: &[u8]) -> Self {
+        let tree_config = SearchConfig {
+            memory_budget: config.memory_budget / n_threads.get(),
+            ..*config
+        };
+        Self {
+            trees: (0..n_threads.get())
+                .map(|_| Tree::from_moves(&tree_config, moves))
+                .collect(),
+            final_selection: config.final_selection,
+            seed: config.seed,
+            n_searches: 0,
+        }
+    }
+
+    pub fn n_threads(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn search_until(&mut self, instant: Instant) {
+        let n_threads = self.trees.len() as u64;
+        let first_stream = self.n_searches * n_threads;
+        self.n_searches += 1;
+
+        // the proof-number search runs once for all trees (they share the root) while the other
+        // trees search, its result is handed to them afterwards
+        let proof_deadline = <Tree>::root_proof_deadline(instant);
+        let (first_tree, other_trees) = self
+            .trees
+            .split_first_mut()
+            .expect("there is at least one tree");
+        let root_proof = thread::scope(|scope| {
+            for (tree_idx, tree) in other_trees.iter_mut().enumerate() {
+                let seed = rng::stream_seed(self.seed, first_stream + 1 + tree_idx as u64);
+                tree.reseed(seed);
+                tree.root_proof_attempted = true;
+                scope.spawn(move || tree.search_until(instant));
+            }
+            first_tree.reseed(rng::stream_seed(self.seed, first_stream));
+            let root_proof =
+                proof_deadline.and_then(|deadline| first_tree.try_prove_root(Some(deadline)));
+            first_tree.search_until(instant);
+            root_proof
+        });
+        if let Some(root_proof) = root_proof {
+            for tree in other_trees {
+                tree.apply_root_proof(root_proof);
+            }
+        }
+    }
+
+    /// visits and scores of the root edges summed over all trees, indexed by move
+    pub fn merged_root_stats(&self) -> [MergedMoveStats; consts::N_CELLS_NESTED as usize] {
+        let mut merged = [MergedMoveStats::default(); consts::N_CELLS_NESTED as usize];
+        for tree in &self.trees {
+            for (move_, visits, child) in tree.explored_root_children() {
+                let stats = &mut merged[move_ as usize];
+                stats.visits += visits as u64;
+                stats.score -= visits as f64 * child.value as f64;
+                stats.score_squared += visits as f64 * child.value_squared as f64;
+                if let Some(proof) = child.proof {
+                    // the child's proof is in favor of the opponent
+                    stats.proven = -proof.score() as i8;
+                }
+            }
+        }
+        merged
+    }
+
+    /// Like [`Tree::best_explored_move`] on the merged statistics: proven wins are played and
+    /// proven losses avoided, otherwise the [`FinalSelection`] decides.
+    pub fn best_explored_move(&self) -> u8 {
+        let merged = self.merged_root_stats();
+        let parent_visits = self
+            .trees
+            .iter()
+            .map(|tree| tree.nodes[tree.root as usize].visits)
+            .sum::<u32>();
+        let parent_visits_ln = (parent_visits as UCBScore).ln();
+        let n_moves = self.trees[0].nodes[self.trees[0].root as usize].child_count;
+        merged
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.visits != 0 || stats.proven != 0)
+            .map(|(move_, stats)| {
+                // NOTE: a move proven by the proof-number search might not be visited yet
+                let visits = stats.visits.max(1);
+                let rating = self.final_selection.rate(&ChildStats {
+                    parent_visits,
+                    parent_visits_ln,
+                    mean_score: (stats.score / visits as f64) as UCBScore,
+                    mean_squared_score: (stats.score_squared / visits as f64) as UCBScore,
+                    visits: visits as u32,
+                    move_: move_ as u8,
+                    n_moves,
+                });
+                (move_, stats.proven, rating)
+            })
+            .max_by(|(_, proven_a, rating_a), (_, proven_b, rating_b)| {
+                proven_a.cmp(proven_b).then(rating_a.total_cmp(rating_b))
+            })
+            .expect("at least one child must have been explored")
+            .0 as u8
+    }
+
+    /// applies the move to all trees, the move does not need to be explored by each of them
+    pub fn apply_move(&mut self, move_: u8) {
+        for tree in &mut self.trees {
+            tree.apply_maybe_explored_move(move_);
+        }
+    }
+
+    /// see [`Tree::compact`]
+    pub fn compact(&mut self) {
+        for tree in &mut self.trees {
+            tree.compact();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        num::NonZeroUsize,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        config::SearchConfig,
+        consts,
+        rng::SearchRng,
+        tree::{
+            Proof, Tree,
+            endgame::{self, EndgameSolver},
+            root_parallel::RootParallelTree,
+            selection::Ucb1,
+        },
+    };
+
+    #[test]
+    fn merges_all_trees() {
+        let mut tree =
+            RootParallelTree::new(&SearchConfig::default(), NonZeroUsize::new(3).unwrap());
+        assert_eq!(tree.n_threads(), 3);
+        tree.search_until(Instant::now() + Duration::from_millis(50));
+
+        let merged_visits: u64 = tree.merged_root_stats().iter().map(|s| s.visits).sum();
+        let root_visits: u64 = tree
+            .trees
+            .iter()
+            .map(|tree| tree.nodes[tree.root as usize].visits as u64)
+            .sum();
+        assert_eq!(merged_visits, root_visits);
+        let memory_used: usize = tree.trees.iter().map(|tree| tree.memory_used()).sum();
+        assert!(memory_used <= SearchConfig::default().memory_budget);
+
+        // every thread must have used a different RNG stream
+        let first_tree_stats: Vec<_> = tree.trees[0].explored_root_children().collect();
+        assert!(tree.trees[1..].iter().all(|other| {
+            other
+                .explored_root_children()
+                .map(|(move_, visits, _)| (move_, visits))
+                .ne(first_tree_stats
+                    .iter()
+                    .map(|(move_, visits, _)| (*move_, *visits)))
+        }));
+
+        let best_move = tree.best_explored_move();
+        assert!((0..consts::N_CELLS_NESTED as u8).contains(&best_move));
+        tree.apply_move(best_move);
+        tree.compact();
+        tree.search_until(Instant::now() + Duration::from_millis(20));
+        let best_move = tree.best_explored_move();
+        tree.apply_move(best_move);
+    }
+
+    #[test]
+    fn proves_the_root_once_for_all_trees() {
+        let mut rng = SearchRng::from_seed(15);
+        let won_state = std::iter::repeat_with(|| endgame::test::random_endgame(12, &mut rng))
+            .flatten()
+            .find(|state| EndgameSolver::new(12).solve(state) == 1)
+            .unwrap();
+        let config = SearchConfig {
+            endgame_empty_cells: 0,
+            ..SearchConfig::default()
+        };
+        let mut tree = RootParallelTree {
+            trees: (0..3)
+                .map(|_| Tree::with_root_state(&config, won_state, Ucb1::default()))
+                .collect(),
+            final_selection: config.final_selection,
+            seed: config.seed,
+            n_searches: 0,
+        };
+        tree.search_until(Instant::now() + Duration::from_millis(20));
+
+        for other in &tree.trees {
+            assert!(other.root_proof_attempted);
+            assert_eq!(other.nodes[other.root as usize].proof, Some(Proof::Win));
+        }
+        let best_move = tree.best_explored_move();
+        let (_, _, best_child) = tree.trees[1]
+            .explored_root_children()
+            .find(|(move_, _, _)| *move_ == best_move)
+            .unwrap();
+        assert_eq!(best_child.proof, Some(Proof::Loss));
+    }
+
+    #[test]
+    fn ranks_merged_moves_by_proof_first() {
+        let mut tree =
+            RootParallelTree::new(&SearchConfig::default(), NonZeroUsize::new(2).unwrap());
+        tree.search_until(Instant::now() + Duration::from_millis(20));
+        let set_proof = |tree: &mut Tree, move_: u8, proof: Proof| {
+            let root = tree.nodes[tree.root as usize];
+            let child_idx = tree.edges[root.first_edge as usize..][..root.child_count as usize]
+                .iter()
+                .find(|edge| edge.move_ == move_)
+                .and_then(|edge| edge.child_node);
+            if let Some(child_idx) = child_idx {
+                tree.nodes[child_idx.get() as usize].proof = Some(proof);
+            }
+        };
+
+        // the opponent wins after the most visited move
+        let most_visited = tree.best_explored_move();
+        for single_tree in &mut tree.trees {
+            set_proof(single_tree, most_visited, Proof::Win);
+        }
+        assert_ne!(tree.best_explored_move(), most_visited);
+
+        // the opponent loses after the least visited move, one tree knows
+        let merged = tree.merged_root_stats();
+        let least_visited = (0..consts::N_CELLS_NESTED as u8)
+            .filter(|move_| merged[*move_ as usize].visits != 0)
+            .min_by_key(|move_| merged[*move_ as usize].visits)
+            .unwrap();
+        let single_tree = tree
+            .trees
+            .iter_mut()
+            .find(|single_tree| {
+                single_tree
+                    .explored_root_children()
+                    .any(|(move_, _, _)| move_ == least_visited)
+            })
+            .unwrap();
+        set_proof(single_tree, least_visited, Proof::Loss);
+        assert_eq!(tree.best_explored_move(), least_visited);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::config::SearchConfig;
+
+pub type UCBScore = f32;
+
+// TODO: determine better value empirically
+pub const EXPLORATION_C: UCBScore = core::f32::consts::SQRT_2;
+
+/// everything a [`SelectionPolicy`] may look at when rating an explored child
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChildStats {
+    pub parent_visits: u32,
+    /// precomputed once per parent as most policies need it
+    pub parent_visits_ln: UCBScore,
+    /// [-1, 1], in favor of the player choosing between the children
+    pub mean_score: UCBScore,
+    /// [0, 1], mean of the squared playout results
+    pub mean_squared_score: UCBScore,
+    /// >= 1, unvisited children are always expanded before any policy is asked
+    pub visits: u32,
+    /// board col-major move leading to the child
+    pub move_: u8,
+    /// number of legal moves of the parent
+    pub n_moves: u8,
+}
+
+/// rates the children of a node during selection, the highest rated child is descended into
+pub trait SelectionPolicy {
+    fn rate(&self, child: &ChildStats) -> UCBScore;
+}
+
+/// https://en.wikipedia.org/wiki/Monte_Carlo_tree_search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ucb1 {
+    pub exploration_c: UCBScore,
+}
+
+impl Default for Ucb1 {
+    fn default() -> Self {
+        Self {
+            exploration_c: EXPLORATION_C,
+        }
+    }
+}
+
+impl Ucb1 {
+    pub fn from_config(config: &SearchConfig) -> Self {
+        Self {
+            exploration_c: config.exploration_c,
+        }
+    }
+}
+
+impl SelectionPolicy for Ucb1 {
+    #[inline]
+    fn rate(&self, child: &ChildStats) -> UCBScore {
+        let exploration =
+            self.exploration_c * UCBScore::sqrt(child.parent_visits_ln / child.visits as UCBScore);
+
+        child.mean_score + exploration
+    }
+}
+
+/// UCB1 with the exploration bounded by the observed variance of the child
+/// (Auer et al. 2002, "Finite-time Analysis of the Multiarmed Bandit Problem")
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Ucb1Tuned;
+
+impl SelectionPolicy for Ucb1Tuned {
+    #[inline]
+    fn rate(&self, child: &ChildStats) -> UCBScore {
+        let visits = child.visits as UCBScore;
+        let log_ratio = child.parent_visits_ln / visits;
+        // the bound is defined for rewards in [0, 1], ours are in [-1, 1] so everything is scaled
+        // by the width of 2 (variance by 4)
+        let variance = child.mean_squared_score - child.mean_score * child.mean_score;
+        let variance_bound = variance / 4.0 + UCBScore::sqrt(2.0 * log_ratio);
+        let exploration = 2.0 * UCBScore::sqrt(log_ratio * variance_bound.min(0.25));
+
+        child.mean_score + exploration
+    }
+}
+
+/// provides the a priori probability of a move being the best one, used by [`Puct`]
+pub trait MovePrior {
+    /// the priors of all moves of a parent should sum up to 1
+    fn prior(&self, move_: u8, n_moves: u8) -> UCBScore;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UniformPrior;
+
+impl MovePrior for UniformPrior {
+    #[inline]
+    fn prior(&self, _move: u8, n_moves: u8) -> UCBScore {
+        1.0 / n_moves as UCBScore
+    }
+}
+
+/// predictor + UCB as popularized by AlphaZero, exploration is guided by the prior of a move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Puct<P: MovePrior = UniformPrior> {
+    pub exploration_c: UCBScore,
+    pub prior: P,
+}
+
+impl<P: MovePrior + Default> Default for Puct<P> {
+    fn default() -> Self {
+        Self {
+            exploration_c: EXPLORATION_C,
+            prior: P::default(),
+        }
+    }
+}
+
+impl<P: MovePrior + Default> Puct<P> {
+    pub fn from_config(config: &SearchConfig) -> Self {
+        Self {
+            exploration_c: config.exploration_c,
+            prior: P::default(),
+        }
+    }
+}
+
+impl<P: MovePrior> SelectionPolicy for Puct<P> {
+    #[inline]
+    fn rate(&self, child: &ChildStats) -> UCBScore {
+        let prior = self.prior.prior(child.move_, child.n_moves);
+        let exploration =
+            self.exploration_c * prior * UCBScore::sqrt(child.parent_visits as UCBScore)
+                / (1 + child.visits) as UCBScore;
+
+        child.mean_score + exploration
+    }
+}
+
+/// How the move is chosen from the children of the root once the search is done.
+/// Proven wins are always played and proven losses avoided, no matter the strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FinalSelection {
+    /// the most visited child
+    #[default]
+    Robust,
+    /// the child with the best mean score
+    Max,
+    /// The most visited child, but a timed search keeps going (up to its hard deadline) until it
+    /// also has the best mean score.
+    RobustMax,
+    /// the child with the best lower confidence bound, see [`SECURE_C`]
+    Secure,
+}
+
+/// weight of the confidence interval of [`FinalSelection::Secure`]
+pub const SECURE_C: UCBScore = 1.0;
+
+impl FinalSelection {
+    /// `robust`, `max`, `robust-max` or `secure`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "robust" => Some(FinalSelection::Robust),
+            "max" => Some(FinalSelection::Max),
+            "robust-max" => Some(FinalSelection::RobustMax),
+            "secure" => Some(FinalSelection::Secure),
+            _ => None,
+        }
+    }
+
+    /// rates an explored child of the root, the highest rated child is played
+    pub fn rate(&self, child: &ChildStats) -> UCBScore {
+        match self {
+            FinalSelection::Robust | FinalSelection::RobustMax => child.visits as UCBScore,
+            FinalSelection::Max => child.mean_score,
+            FinalSelection::Secure => {
+                child.mean_score - SECURE_C / UCBScore::sqrt(child.visits as UCBScore)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree::selection::{
+        ChildStats, FinalSelection, MovePrior, Puct, SelectionPolicy, UCBScore, Ucb1, Ucb1Tuned,
+        UniformPrior,
+    };
+
+    fn child(visits: u32, mean_score: UCBScore, mean_squared_score: UCBScore) -> ChildStats {
+        let parent_visits = 1_000;
+        ChildStats {
+            parent_visits,
+            parent_visits_ln: (parent_visits as UCBScore).ln(),
+            mean_score,
+            mean_squared_score,
+            visits,
+            move_: 0,
+            n_moves: 9,
+        }
+    }
+
+    fn assert_prefers_better_and_less_visited(policy: impl SelectionPolicy) {
+        assert!(policy.rate(&child(100, 0.5, 0.5)) > policy.rate(&child(100, 0.1, 0.5)));
+        assert!(policy.rate(&child(10, 0.1, 0.5)) > policy.rate(&child(500, 0.1, 0.5)));
+    }
+
+    #[test]
+    fn policies_prefer_better_and_less_visited_children() {
+        assert_prefers_better_and_less_visited(Ucb1::default());
+        assert_prefers_better_and_less_visited(Ucb1Tuned);
+        assert_prefers_better_and_less_visited(Puct::<UniformPrior>::default());
+    }
+
+    #[test]
+    fn ucb1_tuned_explores_less_without_variance() {
+        // only draws so far vs. as many wins as losses
+        let certain = child(900, 0.0, 0.0);
+        let uncertain = child(900, 0.0, 1.0);
+        assert!(Ucb1Tuned.rate(&certain) < Ucb1Tuned.rate(&uncertain));
+        assert!(Ucb1Tuned.rate(&uncertain) <= Ucb1::default().rate(&uncertain));
+    }
+
+    #[test]
+    fn puct_follows_prior() {
+        struct OnlyMove0;
+        impl MovePrior for OnlyMove0 {
+            fn prior(&self, move_: u8, _n_moves: u8) -> UCBScore {
+                if move_ == 0 { 1.0 } else { 0.0 }
+            }
+        }
+        let policy = Puct {
+            exploration_c: 1.0,
+            prior: OnlyMove0,
+        };
+        let move_0 = child(100, 0.0, 0.0);
+        let move_1 = ChildStats { move_: 1, ..move_0 };
+        assert!(policy.rate(&move_0) > policy.rate(&move_1));
+        assert_eq!(policy.rate(&move_1), move_1.mean_score);
+    }
+
+    #[test]
+    fn final_selections_weigh_visits_and_scores() {
+        let often_ok = child(900, 0.2, 0.2);
+        let rarely_good = child(10, 0.4, 0.4);
+        let [robust, max, robust_max, secure] = ["robust", "max", "robust-max", "secure"]
+            .map(|name| FinalSelection::from_name(name).unwrap());
+        assert_eq!(FinalSelection::from_name("best"), None);
+
+        assert!(robust.rate(&often_ok) > robust.rate(&rarely_good));
+        assert_eq!(robust_max.rate(&often_ok), robust.rate(&often_ok));
+        assert!(max.rate(&often_ok) < max.rate(&rarely_good));
+        // the better mean of the rarely visited child is not certain enough
+        assert!(secure.rate(&often_ok) > secure.rate(&rarely_good));
+        assert!(secure.rate(&rarely_good) < max.rate(&rarely_good));
+    }
+}
@@ -0,0 +1,745 @@
+use std::{
+    iter,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicI32, AtomicU8, AtomicU32, AtomicU64, Ordering},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    bitmagic,
+    config::SearchConfig,
+    consts,
+    rng::{self, SearchRng},
+    tree::{
+        MonteCarloScore, NodeIdx,
+        node_state::NodeState,
+        selection::{ChildStats, SelectionPolicy, UCBScore, Ucb1},
+    },
+};
+
+/// a visit that is still in flight counts as this many lost playouts
+const VIRTUAL_LOSS: MonteCarloScore = 1;
+/// edges pointing to this index are unexplored, the root is never anyone's child
+const UNEXPLORED: NodeIdx = 0;
+
+/// every field is atomic so that workers can share the arena without locks
+///
+/// The non-statistic fields are written once before the node is published through the
+/// transposition lookup (release) and only read after acquiring it from there or from an edge.
+#[derive(Debug, Default)]
+struct SharedNode {
+    game_state: [AtomicU64; 5],
+    first_edge: AtomicU32,
+    child_count: AtomicU8,
+    visits: AtomicU32,
+    /// sum of the results, in favor of the player to move at this node like in [`super::Tree`]
+    score: AtomicI32,
+    /// sum of the squared results, i.e. the number of decisive ones
+    score_squared: AtomicU32,
+    /// number of workers currently below this node
+    virtual_loss: AtomicU32,
+}
+
+impl SharedNode {
+    fn game_state(&self) -> NodeState {
+        NodeState::from_words(
+            self.game_state
+                .each_ref()
+                .map(|word| word.load(Ordering::Relaxed)),
+        )
+    }
+    fn first_edge(&self) -> usize {
+        self.first_edge.load(Ordering::Relaxed) as usize
+    }
+    fn child_count(&self) -> usize {
+        self.child_count.load(Ordering::Relaxed) as usize
+    }
+}
+
+/// Tree parallelisation: all workers descend the same arena of nodes and edges, virtual loss
+/// spreads them over different paths.
+///
+/// The arena has a fixed capacity, once it is exhausted the workers keep running playouts from
+/// the leaves without growing the tree. [`Self::compact`] frees the nodes abandoned by
+/// [`Self::apply_move`].
+pub struct SharedTree<S: SelectionPolicy = Ucb1> {
+    root: NodeIdx,
+    nodes: Box<[SharedNode]>,
+    n_nodes: AtomicU32,
+    /// child node per edge, [`UNEXPLORED`] if not yet expanded
+    edges: Box<[AtomicU32]>,
+    n_edges: AtomicU32,
+    /// open addressing, [`UNEXPLORED`] marks a free slot
+    lookup_without_root: Box<[AtomicU32]>,
+    n_workers: NonZeroUsize,
+    selection_policy: S,
+    seed: u64,
+    /// used to derive fresh RNG streams for every search
+    n_searches: u64,
+}
+
+/// scratch space of a single worker
+struct Worker {
+    path: Vec<NodeIdx>,
+    edge_selection_buf: [u8; consts::N_CELLS_NESTED as usize],
+    rng: SearchRng,
+}
+
+impl SharedTree {
+    /// empty board, player 1 to move
+    pub fn new(config: &SearchConfig, n_workers: NonZeroUsize, node_capacity: usize) -> Self {
+        Self::new_with_policy(config, n_workers, node_capacity, Ucb1::from_config(config))
+    }
+    /// see [`SharedTree::from_moves_with_policy`]
+    pub fn from_moves(
+        config: &SearchConfig,
+        n_workers: NonZeroUsize,
+        node_capacity: usize,
+        moves: &[u8],
+    ) -> Self {
+        Self::from_moves_with_policy(
+            config,
+            n_workers,
+            node_capacity,
+            moves,
+            Ucb1::from_config(config),
+        )
+    }
+}
+
+impl<S: SelectionPolicy> SharedTree<S> {
+    pub fn new_with_policy(
+        config: &SearchConfig,
+        n_workers: NonZeroUsize,
+        node_capacity: usize,
+        selection_policy: S,
+    ) -> Self {
+        Self::with_root_state(
+            config,
+            n_workers,
+            node_capacity,
+            NodeState::empty(),
+            selection_policy,
+        )
+    }
+    /// A tree for the position after playing `moves` (col-major board moves) from the empty
+    /// board, see [`super::Tree::from_moves_with_policy`].
+    /// # Panics
+    /// if a move is not available or the game is over after the moves
+    pub fn from_moves_with_policy(
+        config: &SearchConfig,
+        n_workers: NonZeroUsize,
+        node_capacity: usize,
+        moves: &[u8],
+        selection_policy: S,
+    ) -> Self {
+        Self::with_root_state(
+            config,
+            n_workers,
+            node_capacity,
+            NodeState::after_moves(moves),
+            selection_policy,
+        )
+    }
+
+    pub(super) fn with_root_state(
+        config: &SearchConfig,
+        n_workers: NonZeroUsize,
+        node_capacity: usize,
+        root_state: NodeState,
+        selection_policy: S,
+    ) -> Self {
+        assert!(node_capacity < NodeIdx::MAX as usize);
+        let edge_capacity = node_capacity * config.guesstimate_avg_children;
+        assert!(edge_capacity < NodeIdx::MAX as usize);
+        // at most half full to keep the probe sequences short
+        let lookup_capacity = (2 * node_capacity).next_power_of_two();
+
+        let mut this = Self {
+            root: 0,
+            nodes: iter::repeat_with(SharedNode::default)
+                .take(node_capacity)
+                .collect(),
+            n_nodes: AtomicU32::new(0),
+            edges: iter::repeat_with(AtomicU32::default)
+                .take(edge_capacity)
+                .collect(),
+            n_edges: AtomicU32::new(0),
+            lookup_without_root: iter::repeat_with(AtomicU32::default)
+                .take(lookup_capacity)
+                .collect(),
+            n_workers,
+            selection_policy,
+            seed: config.seed,
+            n_searches: 0,
+        };
+        this.restart_from(root_state);
+        this
+    }
+
+    /// forgets all nodes, the tree only consists of the root afterwards
+    fn restart_from(&mut self, root_state: NodeState) {
+        self.n_nodes.store(0, Ordering::Relaxed);
+        self.n_edges.store(0, Ordering::Relaxed);
+        for slot in &self.lookup_without_root {
+            slot.store(UNEXPLORED, Ordering::Relaxed);
+        }
+        self.root = self
+            .alloc_node(root_state, false)
+            .expect("capacity must allow at least the root");
+        debug_assert_eq!(self.root, 0);
+    }
+
+    pub fn n_workers(&self) -> NonZeroUsize {
+        self.n_workers
+    }
+
+    /// number of nodes in use, including ones that lost an insertion race
+    pub fn n_nodes(&self) -> usize {
+        (self.n_nodes.load(Ordering::Relaxed) as usize).min(self.nodes.len())
+    }
+
+    /// # Returns
+    /// `None` if the arena is exhausted
+    fn alloc_node(&self, game_state: NodeState, has_won: bool) -> Option<NodeIdx> {
+        // games that are won or settled have no children
+        let child_count = if has_won || game_state.is_settled() {
+            0
+        } else {
+            bitmagic::count_ones_u128(game_state.available_in_board_or_fallback().get())
+        };
+        // NOTE: checking before reserving keeps the counters from growing without bounds
+        if self.n_nodes.load(Ordering::Relaxed) as usize >= self.nodes.len()
+            || (self.n_edges.load(Ordering::Relaxed) + child_count) as usize > self.edges.len()
+        {
+            return None;
+        }
+        let idx = self.n_nodes.fetch_add(1, Ordering::Relaxed);
+        if idx as usize >= self.nodes.len() {
+            return None;
+        }
+        let first_edge = self.n_edges.fetch_add(child_count, Ordering::Relaxed);
+        if (first_edge + child_count) as usize > self.edges.len() {
+            return None;
+        }
+
+        // the slots may be reused after [`Self::compact`]
+        let node = &self.nodes[idx as usize];
+        for (word, value) in node.game_state.iter().zip(game_state.to_words()) {
+            word.store(value, Ordering::Relaxed);
+        }
+        node.first_edge.store(first_edge, Ordering::Relaxed);
+        node.child_count.store(child_count as u8, Ordering::Relaxed);
+        node.visits.store(0, Ordering::Relaxed);
+        node.score.store(0, Ordering::Relaxed);
+        node.score_squared.store(0, Ordering::Relaxed);
+        node.virtual_loss.store(0, Ordering::Relaxed);
+        for edge in &self.edges[first_edge as usize..][..child_count as usize] {
+            edge.store(UNEXPLORED, Ordering::Relaxed);
+        }
+        Some(idx)
+    }
+
+    fn lookup_slot(&self, game_state: &NodeState) -> usize {
+        game_state.zobrist() as usize & (self.lookup_without_root.len() - 1)
+    }
+
+    /// # Returns
+    /// `None` if the arena is exhausted
+    fn get_or_insert_node(&self, previous_state: NodeState, move_: u8) -> Option<NodeIdx> {
+        let (new_node_state, has_won) = previous_state.apply_move(move_);
+
+        let mut slot = self.lookup_slot(&new_node_state);
+        let mut allocated = None;
+        loop {
+            let existing = self.lookup_without_root[slot].load(Ordering::Acquire);
+            if existing == UNEXPLORED {
+                let idx = match allocated {
+                    Some(idx) => idx,
+                    None => *allocated.insert(self.alloc_node(new_node_state, has_won)?),
+                };
+                match self.lookup_without_root[slot].compare_exchange(
+                    UNEXPLORED,
+                    idx,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                ) {
+                    Ok(_) => return Some(idx),
+                    // another worker inserted the same state first, our node is wasted
+                    Err(existing)
+                        if self.nodes[existing as usize].game_state() == new_node_state =>
+                    {
+                        return Some(existing);
+                    }
+                    Err(_) => {}
+                }
+            } else if self.nodes[existing as usize].game_state() == new_node_state {
+                return Some(existing);
+            }
+            slot = (slot + 1) & (self.lookup_without_root.len() - 1);
+        }
+    }
+
+    /// # Returns
+    /// result in favor of the player to move at the given state
+    fn evaluate_new_state(
+        game_state: NodeState,
+        has_won: bool,
+        rng: &mut SearchRng,
+    ) -> MonteCarloScore {
+        if has_won || game_state.is_settled() {
+            game_state.terminal_score()
+        } else {
+            game_state.into_simulation().simulate_random(rng)
+        }
+    }
+
+    /// one selection, expansion, simulation and backpropagation
+    fn playout(&self, worker: &mut Worker) {
+        let Worker {
+            path,
+            edge_selection_buf,
+            rng,
+        } = worker;
+        path.clear();
+
+        let mut node_idx = self.root;
+        // in favor of the player to move at the last node of `path`
+        let score = loop {
+            path.push(node_idx);
+            let node = &self.nodes[node_idx as usize];
+            node.virtual_loss.fetch_add(1, Ordering::Relaxed);
+
+            let game_state = node.game_state();
+            let child_count = node.child_count();
+            if child_count == 0 {
+                break game_state.terminal_score();
+            }
+            let edges = &self.edges[node.first_edge()..node.first_edge() + child_count];
+
+            let mut unvisited_edge_counter = 0;
+            for (relative_edge_idx, _) in edges
+                .iter()
+                .enumerate()
+                .filter(|(_, edge)| edge.load(Ordering::Acquire) == UNEXPLORED)
+            {
+                edge_selection_buf[unvisited_edge_counter] = relative_edge_idx as u8;
+                unvisited_edge_counter += 1;
+            }
+
+            if unvisited_edge_counter != 0 {
+                let rand_idx = rng.rand_in_move_range_exclusive(unvisited_edge_counter as u8);
+                let relative_edge_idx = edge_selection_buf[rand_idx as usize];
+                let move_ = bitmagic::index_of_nth_setbit(
+                    game_state.available_in_board_or_fallback().get(),
+                    relative_edge_idx,
+                ) as u8;
+
+                match self.get_or_insert_node(game_state, move_) {
+                    Some(child_idx) => {
+                        edges[relative_edge_idx as usize].store(child_idx, Ordering::Release);
+                        let child = &self.nodes[child_idx as usize];
+                        child.virtual_loss.fetch_add(1, Ordering::Relaxed);
+                        path.push(child_idx);
+                        break Self::evaluate_new_state(
+                            child.game_state(),
+                            child.child_count() == 0,
+                            rng,
+                        );
+                    }
+                    None => {
+                        // arena exhausted, evaluate the child without storing it
+                        let (child_state, has_won) = game_state.apply_move(move_);
+                        // the child's result is in favor of the opponent
+                        break -Self::evaluate_new_state(child_state, has_won, rng);
+                    }
+                }
+            }
+
+            let parent_visits =
+                node.visits.load(Ordering::Relaxed) + node.virtual_loss.load(Ordering::Relaxed);
+            let parent_visits_ln = (parent_visits as UCBScore).ln();
+            let (mut max_ucb, mut max_ucb_node) = (UCBScore::MIN, UNEXPLORED);
+            let available_moves = game_state.available_in_board_or_fallback();
+            for (edge, move_) in edges.iter().zip(available_moves.iter_moves()) {
+                let child_idx = edge.load(Ordering::Acquire);
+                let child_ucb = self.selection_policy.rate(&child_stats_with_virtual_loss(
+                    parent_visits,
+                    parent_visits_ln,
+                    &self.nodes[child_idx as usize],
+                    move_ as u8,
+                    child_count as u8,
+                ));
+                if child_ucb > max_ucb {
+                    max_ucb = child_ucb;
+                    max_ucb_node = child_idx;
+                }
+            }
+            node_idx = max_ucb_node;
+        };
+
+        let mut score = score;
+        for node_idx in path.iter().rev() {
+            let node = &self.nodes[*node_idx as usize];
+            node.visits.fetch_add(1, Ordering::Relaxed);
+            node.score.fetch_add(score, Ordering::Relaxed);
+            node.score_squared
+                .fetch_add((score * score) as u32, Ordering::Relaxed);
+            node.virtual_loss.fetch_sub(1, Ordering::Relaxed);
+            // negamax
+            score = -score;
+        }
+    }
+
+    fn run_workers(&mut self, keep_going: impl Fn(usize) -> bool + Sync)
+    where
+        S: Sync,
+    {
+        let n_workers = self.n_workers.get() as u64;
+        let first_stream = self.n_searches * n_workers;
+        self.n_searches += 1;
+
+        let this = &*self;
+        let keep_going = &keep_going;
+        thread::scope(|scope| {
+            for worker_idx in 0..n_workers {
+                let seed = rng::stream_seed(this.seed, first_stream + worker_idx);
+                scope.spawn(move || {
+                    let mut worker = Worker {
+                        path: Vec::new(),
+                        edge_selection_buf: [0; consts::N_CELLS_NESTED as usize],
+                        rng: SearchRng::from_seed(seed),
+                    };
+                    let mut n_playouts = 0;
+                    while keep_going(n_playouts) {
+                        this.playout(&mut worker);
+                        n_playouts += 1;
+                    }
+                });
+            }
+        });
+    }
+
+    /// should only be called if root is not at a terminal state
+    pub fn search_n_per_worker(&mut self, n: usize)
+    where
+        S: Sync,
+    {
+        self.run_workers(|n_playouts| n_playouts < n);
+    }
+    pub fn search_until(&mut self, instant: Instant)
+    where
+        S: Sync,
+    {
+        self.run_workers(|_| instant > Instant::now());
+    }
+
+    /// (move, child) for every explored edge of the root
+    fn explored_root_children(&self) -> impl Iterator<Item = (u8, &SharedNode)> {
+        let root_node = &self.nodes[self.root as usize];
+        let available_moves = root_node.game_state().available_in_board_or_fallback();
+        self.edges[root_node.first_edge()..root_node.first_edge() + root_node.child_count()]
+            .iter()
+            .zip(available_moves.iter_moves())
+            .filter_map(|(edge, move_)| {
+                let child_idx = edge.load(Ordering::Acquire);
+                (child_idx != UNEXPLORED).then(|| (move_ as u8, &self.nodes[child_idx as usize]))
+            })
+    }
+
+    pub fn best_explored_move(&self) -> u8 {
+        self.explored_root_children()
+            .max_by_key(|(_, child)| child.visits.load(Ordering::Relaxed))
+            .expect("at least one child must have been explored")
+            .0
+    }
+
+    /// Changes the root by choosing the child with the corresponding move, the move does not
+    /// need to be explored. If there is no room for an unexplored child the tree restarts from
+    /// it, the abandoned nodes are only freed by [`Self::compact`].
+    pub fn apply_move(&mut self, move_: u8) {
+        let root_node = &self.nodes[self.root as usize];
+        let game_state = root_node.game_state();
+        let available_moves = game_state.available_in_board_or_fallback().get();
+        assert!(
+            available_moves & (1 << move_) != 0,
+            "move {move_} is not available"
+        );
+        // the index of the edge is the amount of less significant available moves
+        let edge_idx = root_node.first_edge()
+            + bitmagic::count_ones_u128(available_moves & ((1 << move_) - 1)) as usize;
+
+        let child_idx = self.edges[edge_idx].load(Ordering::Acquire);
+        if child_idx != UNEXPLORED {
+            self.root = child_idx;
+            return;
+        }
+        match self.get_or_insert_node(game_state, move_) {
+            Some(child_idx) => {
+                self.edges[edge_idx].store(child_idx, Ordering::Release);
+                self.root = child_idx;
+            }
+            None => self.restart_from(game_state.apply_move(move_).0),
+        }
+    }
+
+    /// Removes all nodes not reachable from the root (e.g. the sibling branches abandoned by
+    /// [`Self::apply_move`]) and moves the root to index 0, meant to be run between turns.
+    /// Surviving nodes and edges keep their relative order which allows compacting in place.
+    pub fn compact(&mut self) {
+        // nothing was abandoned yet, apart from nodes which lost an insertion race
+        if self.root == 0 {
+            return;
+        }
+        const UNREACHABLE: NodeIdx = NodeIdx::MAX;
+        const REACHABLE: NodeIdx = 0;
+
+        let mut new_idx = vec![UNREACHABLE; self.n_nodes()];
+        let mut to_visit = vec![self.root];
+        new_idx[self.root as usize] = REACHABLE;
+        while let Some(node_idx) = to_visit.pop() {
+            let node = &self.nodes[node_idx as usize];
+            for edge in &self.edges[node.first_edge()..node.first_edge() + node.child_count()] {
+                let child_idx = edge.load(Ordering::Relaxed);
+                if child_idx != UNEXPLORED && new_idx[child_idx as usize] == UNREACHABLE {
+                    new_idx[child_idx as usize] = REACHABLE;
+                    to_visit.push(child_idx);
+                }
+            }
+        }
+
+        // NOTE: the node at index 0 is never reachable (cells can not be un-set), so every
+        // surviving node apart from the root moves to an index <= its old one
+        let mut reachable = Vec::new();
+        for (old_idx, idx) in new_idx.iter_mut().enumerate() {
+            if *idx == REACHABLE && old_idx != self.root as usize {
+                reachable.push(old_idx);
+                *idx = reachable.len() as NodeIdx;
+            }
+        }
+        new_idx[self.root as usize] = 0;
+
+        // workers reserve edges in a different order than nodes, moving the blocks ordered by
+        // their position never overwrites a block that still has to be moved
+        let mut by_first_edge = reachable.clone();
+        by_first_edge.push(self.root as usize);
+        by_first_edge.sort_unstable_by_key(|old_idx| self.nodes[*old_idx].first_edge());
+        let mut edge_cursor = 0;
+        for old_idx in by_first_edge {
+            let node = &self.nodes[old_idx];
+            let first_edge = node.first_edge();
+            for edge_offset in 0..node.child_count() {
+                let child_idx = self.edges[first_edge + edge_offset].load(Ordering::Relaxed);
+                let child_idx = if child_idx == UNEXPLORED {
+                    UNEXPLORED
+                } else {
+                    new_idx[child_idx as usize]
+                };
+                self.edges[edge_cursor + edge_offset].store(child_idx, Ordering::Relaxed);
+            }
+            node.first_edge.store(edge_cursor as u32, Ordering::Relaxed);
+            edge_cursor += node.child_count();
+        }
+
+        self.nodes.swap(0, self.root as usize);
+        for old_idx in reachable.iter().copied() {
+            self.nodes.swap(new_idx[old_idx] as usize, old_idx);
+        }
+        self.root = 0;
+        self.n_nodes
+            .store(reachable.len() as u32 + 1, Ordering::Relaxed);
+        self.n_edges.store(edge_cursor as u32, Ordering::Relaxed);
+
+        for slot in &self.lookup_without_root {
+            slot.store(UNEXPLORED, Ordering::Relaxed);
+        }
+        for node_idx in 1..=reachable.len() {
+            let mut slot = self.lookup_slot(&self.nodes[node_idx].game_state());
+            while self.lookup_without_root[slot].load(Ordering::Relaxed) != UNEXPLORED {
+                slot = (slot + 1) & (self.lookup_without_root.len() - 1);
+            }
+            self.lookup_without_root[slot].store(node_idx as NodeIdx, Ordering::Relaxed);
+        }
+    }
+}
+
+/// the statistics a [`SelectionPolicy`] rates, where every in-flight visit of the child counts as
+/// a lost playout for the player choosing it
+fn child_stats_with_virtual_loss(
+    parent_visits: u32,
+    parent_visits_ln: UCBScore,
+    child: &SharedNode,
+    move_: u8,
+    n_moves: u8,
+) -> ChildStats {
+    let virtual_loss = child.virtual_loss.load(Ordering::Relaxed);
+    // NOTE: a freshly published child might not have any (virtual) visits yet
+    let visits = (child.visits.load(Ordering::Relaxed) + virtual_loss).max(1);
+    // the child's score is in favor of the opponent, who wins the in-flight playouts
+    let score =
+        child.score.load(Ordering::Relaxed) + VIRTUAL_LOSS * virtual_loss as MonteCarloScore;
+    let score_squared = child.score_squared.load(Ordering::Relaxed) + virtual_loss;
+    ChildStats {
+        parent_visits,
+        parent_visits_ln,
+        mean_score: -score as UCBScore / visits as UCBScore,
+        mean_squared_score: score_squared as UCBScore / visits as UCBScore,
+        visits,
+        move_,
+        n_moves,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{num::NonZeroUsize, sync::atomic::Ordering};
+
+    use crate::{
+        config::SearchConfig,
+        consts,
+        tree::{
+            node_state::NodeState,
+            selection::{SelectionPolicy, Ucb1Tuned},
+            shared::{SharedTree, UNEXPLORED},
+        },
+    };
+
+    /// every explored edge leads to the state after its move, no visit is in flight
+    fn assert_consistent<S: SelectionPolicy>(tree: &SharedTree<S>) {
+        for node in &tree.nodes[..tree.n_nodes()] {
+            assert_eq!(node.virtual_loss.load(Ordering::Relaxed), 0);
+            let game_state = node.game_state();
+            let edges = &tree.edges[node.first_edge()..node.first_edge() + node.child_count()];
+            for (edge, move_) in edges
+                .iter()
+                .zip(game_state.available_in_board_or_fallback().iter_moves())
+            {
+                let child_idx = edge.load(Ordering::Relaxed);
+                if child_idx != UNEXPLORED {
+                    assert_eq!(
+                        tree.nodes[child_idx as usize].game_state(),
+                        game_state.apply_move(move_ as u8).0
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_search_keeps_tree_consistent() {
+        let n_workers = 4;
+        let mut tree = SharedTree::new(
+            &SearchConfig::default(),
+            NonZeroUsize::new(n_workers).unwrap(),
+            100_000,
+        );
+        tree.search_n_per_worker(2_000);
+
+        let root = &tree.nodes[tree.root as usize];
+        assert_eq!(
+            root.visits.load(Ordering::Relaxed),
+            n_workers as u32 * 2_000
+        );
+        let children_visits: u32 = tree
+            .explored_root_children()
+            .map(|(_, child)| child.visits.load(Ordering::Relaxed))
+            .sum();
+        assert_eq!(children_visits, root.visits.load(Ordering::Relaxed));
+        assert_consistent(&tree);
+    }
+
+    #[test]
+    fn scores_favor_the_player_to_move() {
+        // player 1 has won boards 0 and 1 and wins the game with 20
+        let moves = [0, 36, 1, 49, 2, 62, 9, 73, 10, 66, 11, 28, 18, 57, 19, 74];
+        let state = moves.iter().fold(NodeState::empty(), |state, move_| {
+            state.apply_move(*move_).0
+        });
+        let mut tree = SharedTree::with_root_state(
+            &SearchConfig::default(),
+            NonZeroUsize::new(2).unwrap(),
+            100_000,
+            state,
+            Ucb1Tuned,
+        );
+        tree.search_n_per_worker(2_000);
+
+        assert_eq!(tree.best_explored_move(), 20);
+        let (_, winning_child) = tree
+            .explored_root_children()
+            .find(|(move_, _)| *move_ == 20)
+            .unwrap();
+        // the opponent is to move after the winning move, and lost
+        assert_eq!(
+            winning_child.score.load(Ordering::Relaxed),
+            -(winning_child.visits.load(Ordering::Relaxed) as i32)
+        );
+        assert!(tree.nodes[tree.root as usize].score.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn search_survives_exhausted_arena() {
+        let mut tree =
+            SharedTree::new(&SearchConfig::default(), NonZeroUsize::new(2).unwrap(), 500);
+        // more playouts than there is room for nodes
+        tree.search_n_per_worker(1_000);
+        assert_eq!(tree.n_nodes(), 500);
+
+        let best_move = tree.best_explored_move();
+        assert!((0..consts::N_CELLS_NESTED as u8).contains(&best_move));
+        tree.apply_move(best_move);
+        tree.search_n_per_worker(100);
+
+        // there is no room for any child, the tree restarts from the chosen one
+        let mut tree = SharedTree::new(&SearchConfig::default(), NonZeroUsize::MIN, 3);
+        tree.search_n_per_worker(100);
+        let unexplored_move = (0..consts::N_CELLS_NESTED as u8)
+            .find(|move_| {
+                tree.explored_root_children()
+                    .all(|(explored, _)| explored != *move_)
+            })
+            .unwrap();
+        tree.apply_move(unexplored_move);
+        assert_eq!(tree.n_nodes(), 1);
+        assert_eq!(
+            tree.nodes[tree.root as usize].game_state(),
+            NodeState::empty().apply_move(unexplored_move).0
+        );
+        tree.search_n_per_worker(100);
+    }
+
+    #[test]
+    fn compaction_keeps_a_game_within_capacity() {
+        let node_capacity = 5_000;
+        let mut tree = SharedTree::from_moves(
+            &SearchConfig::default(),
+            NonZeroUsize::new(2).unwrap(),
+            node_capacity,
+            &[40],
+        );
+        loop {
+            tree.search_n_per_worker(500);
+            let root = &tree.nodes[tree.root as usize];
+            let root_visits = root.visits.load(Ordering::Relaxed);
+            let best_move = tree.best_explored_move();
+            tree.apply_move(best_move);
+            let root = &tree.nodes[tree.root as usize];
+            if root.child_count() == 0 {
+                break;
+            }
+            let (visits, score) = (
+                root.visits.load(Ordering::Relaxed),
+                root.score.load(Ordering::Relaxed),
+            );
+            tree.compact();
+            assert_eq!(tree.root, 0);
+            assert!(tree.n_nodes() <= root_visits as usize);
+            // the subtree of the new root survives
+            let root = &tree.nodes[tree.root as usize];
+            assert_eq!(root.visits.load(Ordering::Relaxed), visits);
+            assert_eq!(root.score.load(Ordering::Relaxed), score);
+            assert_consistent(&tree);
+        }
+    }
+}
@@ -1,31 +1,250 @@
 use crate::{
     bitmagic,
     board::one_bit::OneBitBoard,
-    consts, rng,
-    tree::{MonteCarloScore, NO_MOVE_FORCED},
-    types::Player,
+    consts,
+    rng::SearchRng,
+    tree::{MonteCarloScore, NO_MOVE_FORCED, node_state::NodeState},
+    types::{BoardState, Player},
     util::BoardMajorBitset,
 };
 
+/// picks the moves of a playout, see [`SimulationState::simulate`]
+pub trait RolloutPolicy {
+    /// # Arguments
+    /// - `available_moves`: non-empty, board col-major bitset of the legal moves in `state`
+    /// - `rng`: the generator of the search driving the playout
+    ///
+    /// # Returns
+    /// one of the `available_moves`
+    fn choose_move(
+        &self,
+        state: &SimulationState,
+        available_moves: u128,
+        rng: &mut SearchRng,
+    ) -> u8;
+
+    /// the moves are uniformly random, so a
+    /// [`LaneSimulation`](crate::tree::lane_simulation::LaneSimulation) plays the same playouts
+    const IS_UNIFORM: bool = false;
+}
+
+/// uniformly random playouts, cheapest per move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UniformRollout;
+
+impl RolloutPolicy for UniformRollout {
+    const IS_UNIFORM: bool = true;
+
+    #[inline]
+    fn choose_move(
+        &self,
+        _state: &SimulationState,
+        available_moves: u128,
+        rng: &mut SearchRng,
+    ) -> u8 {
+        random_move(available_moves, rng)
+    }
+}
+
+/// - always takes a move winning the game, otherwise one winning a sub-board
+/// - otherwise avoids sending the opponent to a board where they can win a sub-board
+///   immediately (or anywhere if that board is full)
+/// - otherwise uniformly random
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeuristicRollout;
+
+impl RolloutPolicy for HeuristicRollout {
+    fn choose_move(
+        &self,
+        state: &SimulationState,
+        available_moves: u128,
+        rng: &mut SearchRng,
+    ) -> u8 {
+        let player = state.active_player;
+        let own = state.player_boards[player as usize];
+        let opponent = state.player_boards[player.other() as usize];
+        let free = !(own | opponent);
+
+        let open_boards =
+            !(state.super_boards[0].get() | state.super_boards[1].get() | state.drawn_boards.get())
+                & Self::ALL_BOARDS;
+        let game_winning_boards =
+            winning_cells(state.super_boards[player as usize].get(), open_boards);
+
+        let mut winning_moves = 0;
+        let mut game_winning_moves = 0;
+        let mut opponent_threats: BoardState = 0;
+        for board_idx in 0..consts::N_BOARDS as u8 {
+            let board_free = free.get_sub_board(board_idx).get();
+            if board_free == 0 {
+                continue;
+            }
+            let shift = board_idx as u32 * consts::N_CELLS;
+            let board_winning = winning_cells(own.get_sub_board(board_idx).get(), board_free);
+            winning_moves |= (board_winning as u128) << shift;
+            if game_winning_boards & (1 << board_idx) != 0 {
+                game_winning_moves |= (board_winning as u128) << shift;
+            }
+            if winning_cells(opponent.get_sub_board(board_idx).get(), board_free) != 0 {
+                opponent_threats |= 1 << board_idx;
+            }
+        }
+
+        let game_winning_moves = game_winning_moves & available_moves;
+        if game_winning_moves != 0 {
+            return random_move(game_winning_moves, rng);
+        }
+        let winning_moves = winning_moves & available_moves;
+        if winning_moves != 0 {
+            return random_move(winning_moves, rng);
+        }
+
+        let mut safe_moves = 0;
+        for move_ in BoardMajorBitset::new_truncated(available_moves).iter_moves() {
+            let move_ = move_ as u8;
+            let board_idx = move_ / consts::N_CELLS as u8;
+            let target_board = move_ % consts::N_CELLS as u8;
+            let mut target_free = free.get_sub_board(target_board).get();
+            let mut target_threatened = opponent_threats & (1 << target_board) != 0;
+            if board_idx == target_board {
+                // the move itself occupies a cell of the target, possibly blocking the threat
+                target_free &= !(1 << (move_ % consts::N_CELLS as u8));
+                target_threatened =
+                    winning_cells(opponent.get_sub_board(target_board).get(), target_free) != 0;
+            }
+            let is_safe = if target_free == 0 {
+                // the opponent may play anywhere
+                opponent_threats == 0
+            } else {
+                !target_threatened
+            };
+            if is_safe {
+                safe_moves |= 1 << move_;
+            }
+        }
+
+        if safe_moves != 0 {
+            random_move(safe_moves, rng)
+        } else {
+            random_move(available_moves, rng)
+        }
+    }
+}
+
+impl HeuristicRollout {
+    const ALL_BOARDS: BoardState = 0b1_1111_1111;
+}
+
+/// uniformly random set bit of a non-empty board col-major bitset
+#[inline]
+pub(super) fn random_move(moves: u128, rng: &mut SearchRng) -> u8 {
+    let n_moves = bitmagic::count_ones_u128(moves) as u8;
+    let rand_nth_setbit = rng.rand_in_move_range_exclusive(n_moves);
+    bitmagic::index_of_nth_setbit(moves, rand_nth_setbit) as u8
+}
+
+/// # Returns
+/// the `free` cells of a sub-board (or boards of a super-board) which would complete a line of
+/// `own`
+fn winning_cells(own: BoardState, free: BoardState) -> BoardState {
+    consts::WINNER_MASKS_1BIT.iter().fold(0, |winning, mask| {
+        let missing = mask & !own;
+        if missing.count_ones() == 1 && missing & free != 0 {
+            winning | missing
+        } else {
+            winning
+        }
+    })
+}
+
+/// The result of a game in which no player can complete a super board line anymore (see
+/// [`any_line_completable`]), so only the number of won boards decides it.
+///
+/// # Arguments
+/// indexed by [`Player`]: the occupied cells and the won boards of each player
+///
+/// # Returns
+/// the result in favor of `in_favor_of` if the boards still open can not change it, i.e. one
+/// player has won more boards than the other can still reach or no board can be won anymore.
+/// `None` if the open boards can still change the result.
+pub(super) fn settled_score(
+    occupied: [u128; 2],
+    super_boards: [BoardState; 2],
+    drawn_boards: BoardState,
+    in_favor_of: Player,
+) -> Option<MonteCarloScore> {
+    debug_assert!(!any_line_completable(super_boards, drawn_boards));
+    let open_boards =
+        !(super_boards[0] | super_boards[1] | drawn_boards) & HeuristicRollout::ALL_BOARDS;
+    // an open board can be won while one of its lines has no cell of the opponent
+    let most_boards = [Player::Player1, Player::Player2].map(|player| {
+        let opponent = occupied[player.other() as usize];
+        let winnable_boards = (0..consts::N_BOARDS as u8)
+            .filter(|board_idx| {
+                open_boards & (1 << board_idx) != 0
+                    && OneBitBoard::new(
+                        (opponent >> (*board_idx as u32 * consts::N_CELLS)) as BoardState,
+                    )
+                    .free_lines()
+                        != 0
+            })
+            .count() as u32;
+        bitmagic::count_ones_u32(super_boards[player as usize]) + winnable_boards
+    });
+    let won_boards = super_boards.map(bitmagic::count_ones_u32);
+
+    let (favored, other) = (in_favor_of as usize, in_favor_of.other() as usize);
+    if won_boards[favored] > most_boards[other] {
+        Some(1)
+    } else if won_boards[other] > most_boards[favored] {
+        Some(-1)
+    } else if won_boards == most_boards {
+        // neither can gain a board and neither is ahead
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// whether any player can still complete a super board line, see
+/// [`SimulationState::completable_lines`]
+pub(super) fn any_line_completable(
+    super_boards: [BoardState; 2],
+    drawn_boards: BoardState,
+) -> bool {
+    OneBitBoard::new(super_boards[0] | drawn_boards).free_lines()
+        | OneBitBoard::new(super_boards[1] | drawn_boards).free_lines()
+        != 0
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(super) struct SimulationState {
+pub struct SimulationState {
     player_boards: [BoardMajorBitset; 2],
     /// contains information on who won which sub-board
     super_boards: [OneBitBoard; 2],
+    /// the sub-boards that were filled without a winner
+    drawn_boards: OneBitBoard,
     active_player: Player,
     forced_board: u8,
 }
 
 impl SimulationState {
+    /// the empty board with player 1 to move
+    pub fn empty() -> Self {
+        NodeState::empty().into_simulation()
+    }
+
     pub(super) fn new(
         player_boards: [BoardMajorBitset; 2],
         super_boards: [OneBitBoard; 2],
+        drawn_boards: OneBitBoard,
         active_player: Player,
         forced_board: u8,
     ) -> Self {
         Self {
             player_boards,
             super_boards,
+            drawn_boards,
             active_player,
             forced_board,
         }
@@ -38,6 +257,63 @@ impl SimulationState {
         self.player_boards[Player::Player2 as usize]
     }
 
+    pub fn active_player(&self) -> Player {
+        self.active_player
+    }
+    /// [`NO_MOVE_FORCED`] if the player may move anywhere
+    pub fn forced_board(&self) -> u8 {
+        self.forced_board
+    }
+    /// board col-major bitset of the cells occupied by the player, won sub-boards are filled
+    pub fn occupied_by(&self, player: Player) -> u128 {
+        self.player_boards[player as usize].get()
+    }
+    /// the sub-boards won by the player
+    pub fn super_board(&self, player: Player) -> OneBitBoard {
+        self.super_boards[player as usize]
+    }
+    /// the sub-boards that were filled without a winner
+    pub fn drawn_boards(&self) -> OneBitBoard {
+        self.drawn_boards
+    }
+    /// # Returns
+    /// bitset over [`consts::WINNER_MASKS_1BIT`] of the super board lines the player can still
+    /// complete, i.e. lines without a board won by the opponent or drawn
+    pub fn completable_lines(&self, player: Player) -> u8 {
+        OneBitBoard::new(self.super_boards[player.other() as usize].get() | self.drawn_boards.get())
+            .free_lines()
+    }
+    /// the result once no super board line can be completed and the open boards can not change
+    /// it anymore, see [`settled_score`]
+    pub(super) fn settled_score(&self, in_favor_of: Player) -> Option<MonteCarloScore> {
+        if self.any_line_completable() {
+            return None;
+        }
+        settled_score(
+            self.player_boards.map(|board| board.get()),
+            self.super_boards.map(|board| board.get()),
+            self.drawn_boards.get(),
+            in_favor_of,
+        )
+    }
+    fn any_line_completable(&self) -> bool {
+        any_line_completable(
+            self.super_boards.map(|board| board.get()),
+            self.drawn_boards.get(),
+        )
+    }
+    /// the won and drawn sub-boards
+    fn decided_boards(&self) -> BoardState {
+        self.super_boards[0].get() | self.super_boards[1].get() | self.drawn_boards.get()
+    }
+    /// every sub-board is won or drawn, so there are no moves left
+    pub fn all_boards_decided(&self) -> bool {
+        OneBitBoard::new(
+            self.super_boards[0].get() | self.super_boards[1].get() | self.drawn_boards.get(),
+        )
+        .is_full()
+    }
+
     fn has_won(&self, player: Player) -> bool {
         self.super_boards[player as usize].has_won()
     }
@@ -47,7 +323,7 @@ impl SimulationState {
     /// # Returns
     /// - new node state with move applied (and board bits won if board was won)
     /// - true if the active player won using this move
-    pub(super) fn apply_move(self, board_col_major_idx: u8) -> (Self, bool) {
+    pub fn apply_move(self, board_col_major_idx: u8) -> (Self, bool) {
         let mut child_state = self;
         let player = self.active_player;
 
@@ -67,6 +343,10 @@ impl SimulationState {
             child_state.player_boards[player as usize].fill_board(board_idx);
             // track wins in super board
             child_state.super_boards[player as usize].set_cell(board_idx);
+        } else if (child_state.player1_occupied() | child_state.player2_occupied())
+            .is_board_full(board_idx)
+        {
+            child_state.drawn_boards.set_cell(board_idx);
         }
 
         let won_game = if has_won_subboard {
@@ -78,7 +358,7 @@ impl SimulationState {
         (child_state, won_game)
     }
 
-    pub(super) fn available_in_board_or_fallback(&self) -> BoardMajorBitset {
+    pub(crate) fn available_in_board_or_fallback(&self) -> BoardMajorBitset {
         // TODO PERF: this code has an unnecessary '& GRID_MASK', check the asm
         let is_occupied = self.player1_occupied() | self.player2_occupied();
         let is_available = !is_occupied;
@@ -100,11 +380,63 @@ impl SimulationState {
         }
     }
 
+    /// see [`Self::simulate`]
+    pub fn simulate_random(self, rng: &mut SearchRng) -> MonteCarloScore {
+        self.simulate(&UniformRollout, rng)
+    }
+
+    /// Plays until the game ends choosing moves with the `rollout_policy`. Once no player can
+    /// complete a super board line anymore the playout ends as soon as the boards still winnable
+    /// can not change the result, see [`settled_score`].
     /// # Returns
     /// - -1 if the not initially active player wins
     /// - 0 for a draw
     /// - 1 if the initally active player wins
-    pub(super) fn simulate_random(mut self) -> MonteCarloScore {
+    pub(super) fn simulate<R: RolloutPolicy>(
+        mut self,
+        rollout_policy: &R,
+        rng: &mut SearchRng,
+    ) -> MonteCarloScore {
+        self.play_out(rollout_policy, rng)
+    }
+
+    /// plays `n_playouts` from this state, each on a fresh copy of it
+    /// # Returns
+    /// the sum of the results, see [`Self::simulate`]
+    pub(super) fn simulate_n<R: RolloutPolicy>(
+        &self,
+        n_playouts: u32,
+        rollout_policy: &R,
+        rng: &mut SearchRng,
+    ) -> MonteCarloScore {
+        (0..n_playouts)
+            .map(|_| self.simulate(rollout_policy, rng))
+            .sum()
+    }
+
+    /// Like [`Self::simulate_n`], but all playouts run in `buffer`, which is reset to this state
+    /// before each of them.
+    pub(super) fn simulate_n_in<R: RolloutPolicy>(
+        &self,
+        buffer: &mut Self,
+        n_playouts: u32,
+        rollout_policy: &R,
+        rng: &mut SearchRng,
+    ) -> MonteCarloScore {
+        let mut score_sum = 0;
+        for _ in 0..n_playouts {
+            *buffer = *self;
+            score_sum += buffer.play_out(rollout_policy, rng);
+        }
+        score_sum
+    }
+
+    /// [`Self::simulate`] in place, the state is at the end of the game afterwards
+    fn play_out<R: RolloutPolicy>(
+        &mut self,
+        rollout_policy: &R,
+        rng: &mut SearchRng,
+    ) -> MonteCarloScore {
         debug_assert!(!self.super_boards[0].has_won());
         debug_assert!(!self.super_boards[1].has_won());
         let mut has_won = false;
@@ -115,12 +447,23 @@ impl SimulationState {
             "can not simulate from a terminal state"
         );
 
-        while !(has_won || available_moves.is_empty()) {
-            let n_moves = bitmagic::count_ones_u128(available_moves.get()) as u8;
-            let rand_nth_setbit = rng::rand_in_move_range_exclusive(n_moves);
-            let rand_move =
-                bitmagic::index_of_nth_setbit(available_moves.get(), rand_nth_setbit) as u8;
-            (self, has_won) = self.apply_move(rand_move);
+        // only changes when a board is won or drawn
+        let mut line_completable = self.any_line_completable();
+        // any move can block the last line of an open board, so this is checked after every move
+        // once no line is completable
+        let mut settled = self.settled_score(inital_player);
+
+        while !(has_won || settled.is_some()) {
+            let move_ = rollout_policy.choose_move(self, available_moves.get(), rng);
+            debug_assert!(available_moves.get() & (1 << move_) != 0);
+            let decided_boards = self.decided_boards();
+            (*self, has_won) = self.apply_move(move_);
+            if self.decided_boards() != decided_boards {
+                line_completable = self.any_line_completable();
+            }
+            if !line_completable {
+                settled = self.settled_score(inital_player);
+            }
 
             available_moves = self.available_in_board_or_fallback();
         }
@@ -129,15 +472,164 @@ impl SimulationState {
             let winner = self.active_player.other();
             if winner == inital_player { 1 } else { -1 }
         } else {
-            let won_board_initial_player =
-                bitmagic::count_ones_u32(self.super_boards[inital_player as usize].get());
-            let won_board_other_player =
-                bitmagic::count_ones_u32(self.super_boards[inital_player.other() as usize].get());
-            match Ord::cmp(&won_board_initial_player, &won_board_other_player) {
-                std::cmp::Ordering::Less => -1,
-                std::cmp::Ordering::Equal => 0,
-                std::cmp::Ordering::Greater => 1,
+            settled.expect("the loop only ends once the game is won or settled")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        board::one_bit::OneBitBoard,
+        consts,
+        rng::SearchRng,
+        tree::{
+            NO_MOVE_FORCED,
+            node_state::NodeState,
+            simulation::{HeuristicRollout, RolloutPolicy, SimulationState, UniformRollout},
+        },
+        types::{BoardState, Player},
+        util::BoardMajorBitset,
+    };
+
+    fn simulation_after(moves: &[u8]) -> SimulationState {
+        moves
+            .iter()
+            .fold(NodeState::empty(), |state, move_| {
+                state.apply_move(*move_).0
+            })
+            .into_simulation()
+    }
+
+    /// player 1 to move anywhere, all other boards are empty
+    fn state_with_boards(won: [BoardState; 2], drawn: BoardState) -> SimulationState {
+        let mut player_boards = [BoardMajorBitset::default(); 2];
+        for board_idx in 0..consts::N_BOARDS as u8 {
+            let shift = board_idx as u32 * consts::N_CELLS;
+            for player in 0..2 {
+                if won[player] & (1 << board_idx) != 0 {
+                    player_boards[player].fill_board(board_idx);
+                }
             }
+            if drawn & (1 << board_idx) != 0 {
+                // x x o / x o o / o x x
+                player_boards[0] =
+                    player_boards[0] | BoardMajorBitset::new_truncated(0b1_0110_0011 << shift);
+                player_boards[1] =
+                    player_boards[1] | BoardMajorBitset::new_truncated(0b0_1001_1100 << shift);
+            }
+        }
+        SimulationState::new(
+            player_boards,
+            won.map(OneBitBoard::new),
+            OneBitBoard::new(drawn),
+            Player::Player1,
+            NO_MOVE_FORCED,
+        )
+    }
+
+    fn choose(state: &SimulationState, rng: &mut SearchRng) -> u8 {
+        HeuristicRollout.choose_move(state, state.available_in_board_or_fallback().get(), rng)
+    }
+
+    #[test]
+    fn heuristic_takes_winning_moves() {
+        // player 1 to move in board 2 with 18 and 19 already set, 20 wins board 2
+        let state = simulation_after(&[18, 2, 19, 11]);
+        assert_eq!(state.active_player(), Player::Player1);
+        assert_eq!(state.forced_board(), 2);
+        let mut rng = SearchRng::default();
+        for _ in 0..100 {
+            assert_eq!(choose(&state, &mut rng), 20);
+        }
+    }
+
+    #[test]
+    fn heuristic_prefers_winning_the_game() {
+        // player 1 has won boards 0 and 1 and is forced into board 2 where 20 wins the game
+        let state = simulation_after(&[0, 36, 1, 49, 2, 62, 9, 73, 10, 66, 11, 28, 18, 57, 19, 74]);
+        assert_eq!(state.forced_board(), 2);
+        let mut rng = SearchRng::default();
+        for _ in 0..100 {
+            assert_eq!(choose(&state, &mut rng), 20);
+        }
+    }
+
+    #[test]
+    fn heuristic_avoids_sending_opponent_to_a_win() {
+        // player 2 has 27 and 28 in board 3 and could win it with 29, player 1 must not play
+        // 12 (cell 3 of board 1)
+        let state = simulation_after(&[3, 27, 5, 47, 21, 28]);
+        assert_eq!(state.active_player(), Player::Player1);
+        assert_eq!(state.forced_board(), 1);
+        let available = state.available_in_board_or_fallback().get();
+        assert_ne!(available & (1 << 12), 0);
+        let mut rng = SearchRng::default();
+        for _ in 0..200 {
+            let move_ = choose(&state, &mut rng);
+            assert_ne!(move_ % 9, 3, "sent the opponent to board 3 with {move_}");
         }
     }
+
+    #[test]
+    fn both_policies_finish_playouts() {
+        let state = simulation_after(&[40]);
+        let mut rng = SearchRng::default();
+        for _ in 0..100 {
+            assert!((-1..=1).contains(&state.simulate(&UniformRollout, &mut rng)));
+            assert!((-1..=1).contains(&state.simulate(&HeuristicRollout, &mut rng)));
+        }
+    }
+
+    #[test]
+    fn batched_playouts_do_not_depend_on_the_buffer() {
+        let state = simulation_after(&[40]);
+        let (mut rng, mut buffer_rng) = (SearchRng::from_seed(22), SearchRng::from_seed(22));
+        let mut buffer = SimulationState::empty();
+        for _ in 0..10 {
+            let score_sum = state.simulate_n(8, &UniformRollout, &mut rng);
+            assert!((-8..=8).contains(&score_sum));
+            assert_eq!(
+                state.simulate_n_in(&mut buffer, 8, &UniformRollout, &mut buffer_rng),
+                score_sum
+            );
+        }
+        // the buffer holds the end of the last playout
+        assert_ne!(buffer, state);
+    }
+
+    #[test]
+    fn hopeless_playouts_end_once_settled() {
+        // boards 0 and 4 are drawn, player 1 won 2 and 6 and player 2 won 8: every line of the
+        // super board contains a drawn board or boards of both players
+        let state = state_with_boards([0b0_0100_0100, 0b1_0000_0000], 0b0_0001_0001);
+        assert_eq!(state.completable_lines(Player::Player1), 0);
+        assert_eq!(state.completable_lines(Player::Player2), 0);
+        // player 2 may still win all 4 open boards
+        assert_eq!(state.settled_score(Player::Player1), None);
+
+        let mut rng = SearchRng::default();
+        let mut buffer = SimulationState::empty();
+        // losses, draws and wins of player 1
+        let mut outcomes = [0; 3];
+        for _ in 0..100 {
+            let result = state.simulate_n_in(&mut buffer, 1, &UniformRollout, &mut rng);
+            // played on until the open boards can not change the result anymore
+            assert_ne!(buffer, state);
+            assert_eq!(buffer.settled_score(Player::Player1), Some(result));
+            outcomes[(result + 1) as usize] += 1;
+        }
+        // being ahead on boards does not decide the game yet
+        assert!(outcomes[0] > 0, "{outcomes:?}");
+
+        // with board 5 won and boards 1 and 3 drawn, player 2 can reach 2 boards at most
+        let settled = state_with_boards([0b0_0110_0100, 0b1_0000_0000], 0b0_0001_1011);
+        assert_eq!(settled.settled_score(Player::Player1), Some(1));
+        assert_eq!(settled.settled_score(Player::Player2), Some(-1));
+        assert_eq!(
+            settled.simulate_n_in(&mut buffer, 1, &UniformRollout, &mut rng),
+            1
+        );
+        assert_eq!(buffer, settled);
+    }
 }
@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+use crate::tree::{Tree, selection::SelectionPolicy, simulation::RolloutPolicy};
+
+/// what the tree knows about one move of the root
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveStats {
+    /// board col-major
+    pub move_: u8,
+    /// how often the move was chosen from the root
+    pub visits: u32,
+    /// [-1, 1], in favor of the player to move at the root
+    pub mean_score: f32,
+    /// number of legal moves after this one
+    pub child_count: u8,
+}
+
+/// summary of the tree and of the most recent `search*`/`ponder` call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchStats {
+    /// nodes in the arena, including ones only reachable from abandoned roots until compacted
+    pub total_nodes: usize,
+    /// iterations (playouts) of the last search
+    pub iterations: u64,
+    /// deepest leaf reached by the last search, relative to the root
+    pub max_depth: u32,
+    pub average_depth: f32,
+    pub duration: Duration,
+    /// the memory budget is exhausted, see [`Tree::is_degraded`]
+    pub degraded: bool,
+}
+
+impl SearchStats {
+    /// iterations of the last search per second, comparable to the nps of other engines
+    pub fn nodes_per_second(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+        self.iterations as f64 / self.duration.as_secs_f64()
+    }
+}
+
+/// bookkeeping for [`SearchStats`], reset by every search
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct LastSearch {
+    started: Option<Instant>,
+    duration: Duration,
+    iterations: u64,
+    depth_sum: u64,
+    max_depth: u32,
+}
+
+impl LastSearch {
+    pub(super) fn start() -> Self {
+        Self {
+            started: Some(Instant::now()),
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn record(&mut self, depth: u32) {
+        self.iterations += 1;
+        self.depth_sum += depth as u64;
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    pub(super) fn finish(&mut self) {
+        if let Some(started) = self.started {
+            self.duration = started.elapsed();
+        }
+    }
+}
+
+impl<S: SelectionPolicy, R: RolloutPolicy> Tree<S, R> {
+    /// statistics of every explored move of the root, in move order
+    pub fn root_move_stats(&self) -> Vec<MoveStats> {
+        self.explored_root_children()
+            .map(|(move_, visits, child)| MoveStats {
+                move_,
+                visits,
+                // the child's value is in favor of the opponent
+                mean_score: -child.value,
+                child_count: child.child_count,
+            })
+            .collect()
+    }
+
+    /// the most visited line starting at the root, at most `max_depth` moves long
+    pub fn principal_variation(&self, max_depth: usize) -> Vec<u8> {
+        let mut line = Vec::with_capacity(max_depth);
+        let mut node = &self.nodes[self.root as usize];
+        while line.len() < max_depth {
+            let edges = &self.edges
+                [node.first_edge as usize..(node.first_edge as usize + node.child_count as usize)];
+            let Some((move_, child_node)) = edges
+                .iter()
+                .filter(|edge| edge.visits != 0)
+                .max_by_key(|edge| edge.visits)
+                .and_then(|edge| edge.child_node.map(|child_node| (edge.move_, child_node)))
+            else {
+                break;
+            };
+            line.push(move_);
+            node = &self.nodes[child_node.get() as usize];
+        }
+        line
+    }
+
+    pub fn search_stats(&self) -> SearchStats {
+        let last_search = &self.last_search;
+        SearchStats {
+            total_nodes: self.nodes.len(),
+            iterations: last_search.iterations,
+            max_depth: last_search.max_depth,
+            average_depth: if last_search.iterations == 0 {
+                0.0
+            } else {
+                last_search.depth_sum as f32 / last_search.iterations as f32
+            },
+            duration: last_search.duration,
+            degraded: self.is_degraded(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree::Tree;
+
+    #[test]
+    fn stats_describe_the_last_search() {
+        let mut tree = Tree::default();
+        tree.search_n(5_000);
+
+        let move_stats = tree.root_move_stats();
+        assert_eq!(move_stats.len(), 81);
+        assert_eq!(
+            move_stats.iter().map(|stats| stats.visits).sum::<u32>(),
+            5_000
+        );
+        assert!(
+            move_stats
+                .iter()
+                .all(|stats| (-1.0..=1.0).contains(&stats.mean_score) && stats.child_count > 0)
+        );
+
+        let pv = tree.principal_variation(4);
+        assert_eq!(pv.len(), 4);
+        assert_eq!(pv[0], tree.best_explored_move());
+        // every move of the line is played in the board forced by the previous one
+        for window in pv.windows(2) {
+            assert_eq!(window[1] / 9, window[0] % 9);
+        }
+
+        let stats = tree.search_stats();
+        assert_eq!(stats.iterations, 5_000);
+        assert_eq!(stats.total_nodes, tree.nodes.len());
+        assert!(stats.max_depth >= 2);
+        assert!(stats.average_depth >= 1.0 && stats.average_depth <= stats.max_depth as f32);
+        assert!(stats.nodes_per_second() > 0.0);
+        assert!(!stats.degraded);
+
+        tree.search_n(10);
+        assert_eq!(tree.search_stats().iterations, 10);
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+/// what the search reports to the [`TimeManager`] about the root
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchProgress {
+    /// legal moves at the root
+    pub n_moves: u8,
+    /// the root is proven and a move achieving the proof is known
+    pub solved: bool,
+    /// edge visits of the most visited root move
+    pub most_visits: u32,
+    /// edge visits of the runner-up, 0 if there is none
+    pub second_most_visits: u32,
+    /// iterations per second of the running search, every iteration visits one root edge
+    pub iterations_per_second: f32,
+    /// the final selection does not accept the move yet, see
+    /// [`crate::tree::selection::FinalSelection::RobustMax`]
+    pub disputed: bool,
+}
+
+/// Decides when a search for our move is done.
+///
+/// All times are measured from when the opponent's move was read, so the time spent on IO and
+/// before the search started counts against the turn as well. A search normally ends at the soft
+/// deadline, earlier once the most visited move can not be overtaken anymore and later (up to the
+/// hard deadline) if the two most visited moves are close or the final selection disputes the
+/// move. The most visited move is the one played, so there is no point in searching once it is
+/// settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeManager {
+    input_read: Instant,
+    soft_deadline: Instant,
+    hard_deadline: Instant,
+}
+
+impl TimeManager {
+    /// the share of the budget a search without close moves takes
+    const SOFT_BUDGET_NUMERATOR: u32 = 3;
+    const SOFT_BUDGET_DENOMINATOR: u32 = 4;
+    /// the runner-up is close if it has at least this share of the visits of the most visited move
+    const CLOSE_VISITS_RATIO: f32 = 0.8;
+
+    /// # Arguments
+    /// - `input_read`: when the opponent's move was read, the turn timer started then
+    /// - `budget`: the time we may use at most, see [`crate::config::SearchConfig::turn_budget`]
+    pub fn new(input_read: Instant, budget: Duration) -> Self {
+        Self {
+            input_read,
+            soft_deadline: input_read
+                + budget * Self::SOFT_BUDGET_NUMERATOR / Self::SOFT_BUDGET_DENOMINATOR,
+            hard_deadline: input_read + budget,
+        }
+    }
+
+    /// when a search without close moves ends at the latest
+    pub fn soft_deadline(&self) -> Instant {
+        self.soft_deadline
+    }
+
+    /// when every search ends, no matter what
+    pub fn hard_deadline(&self) -> Instant {
+        self.hard_deadline
+    }
+
+    /// time since the opponent's move was read
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.input_read)
+    }
+
+    fn is_close(progress: &SearchProgress) -> bool {
+        progress.second_most_visits as f32 >= Self::CLOSE_VISITS_RATIO * progress.most_visits as f32
+    }
+
+    /// # Returns
+    /// whether the search should stop at `now`
+    pub fn should_stop(&self, now: Instant, progress: &SearchProgress) -> bool {
+        if now >= self.hard_deadline || progress.n_moves <= 1 || progress.solved {
+            return true;
+        }
+        if progress.disputed {
+            return false;
+        }
+        let deadline = if Self::is_close(progress) {
+            self.hard_deadline
+        } else {
+            self.soft_deadline
+        };
+        if now >= deadline {
+            return true;
+        }
+        // even if every remaining iteration went to the runner-up, it would stay behind
+        let remaining_iterations =
+            deadline.duration_since(now).as_secs_f32() * progress.iterations_per_second;
+        ((progress.most_visits - progress.second_most_visits) as f32) > remaining_iterations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::tree::time_manager::{SearchProgress, TimeManager};
+
+    const UNDECIDED: SearchProgress = SearchProgress {
+        n_moves: 9,
+        solved: false,
+        most_visits: 1_000,
+        second_most_visits: 100,
+        iterations_per_second: 100_000.0,
+        disputed: false,
+    };
+
+    #[test]
+    fn stops_at_the_deadlines() {
+        let input_read = Instant::now();
+        let time_manager = TimeManager::new(input_read, Duration::from_millis(80));
+        assert_eq!(
+            time_manager.soft_deadline(),
+            input_read + Duration::from_millis(60)
+        );
+        assert_eq!(
+            time_manager.elapsed(input_read + Duration::from_millis(5)),
+            Duration::from_millis(5)
+        );
+
+        assert!(!time_manager.should_stop(input_read, &UNDECIDED));
+        assert!(time_manager.should_stop(time_manager.soft_deadline(), &UNDECIDED));
+
+        let close = SearchProgress {
+            second_most_visits: 900,
+            ..UNDECIDED
+        };
+        // close moves get the extra time, but not more
+        assert!(!time_manager.should_stop(time_manager.soft_deadline(), &close));
+        assert!(time_manager.should_stop(time_manager.hard_deadline(), &close));
+
+        let disputed = SearchProgress {
+            disputed: true,
+            ..UNDECIDED
+        };
+        assert!(!time_manager.should_stop(time_manager.soft_deadline(), &disputed));
+        assert!(time_manager.should_stop(time_manager.hard_deadline(), &disputed));
+    }
+
+    #[test]
+    fn stops_early_when_decided() {
+        let input_read = Instant::now();
+        let time_manager = TimeManager::new(input_read, Duration::from_millis(80));
+
+        let only_move = SearchProgress {
+            n_moves: 1,
+            ..UNDECIDED
+        };
+        assert!(time_manager.should_stop(input_read, &only_move));
+        let solved = SearchProgress {
+            solved: true,
+            ..UNDECIDED
+        };
+        assert!(time_manager.should_stop(input_read, &solved));
+
+        // 900 visits ahead with 100 iterations left until the soft deadline
+        let almost_done = time_manager.soft_deadline() - Duration::from_millis(1);
+        assert!(time_manager.should_stop(almost_done, &UNDECIDED));
+        let fast = SearchProgress {
+            iterations_per_second: 10_000_000.0,
+            ..UNDECIDED
+        };
+        assert!(!time_manager.should_stop(almost_done, &fast));
+    }
+}
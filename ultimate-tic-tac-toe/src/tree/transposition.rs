@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use crate::{
+    rng::SearchRng,
+    tree::{NodeIdx, node_state::NodeState, simulation},
+};
+
+/// marks a free slot, index 0 is the initial root (or a placeholder) and never stored
+const EMPTY: NodeIdx = 0;
+
+/// [63:32] = upper half of the zobrist hash, rejects most mismatches without touching the node
+/// [31:0] = node index, [`EMPTY`] for a free slot
+///
+/// NOTE: a plain integer so that the table can be allocated (and cleared) as zeroed memory
+type Slot = u64;
+
+const fn slot_node(slot: Slot) -> NodeIdx {
+    slot as NodeIdx
+}
+
+/// result of [`TranspositionTable::probe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Probe {
+    Found(NodeIdx),
+    /// the state is not stored, it would be inserted at this slot
+    Vacant(usize),
+}
+
+/// Open addressing (linear probing) map from [`NodeState`] to [`NodeIdx`] keyed by the zobrist
+/// hash. The states themselves only live in the nodes of the tree, so every hit is verified
+/// against the full state through the `state_of` callbacks.
+#[derive(Debug, Clone)]
+pub(super) struct TranspositionTable {
+    /// power of two, at most half full to keep the probe sequences short
+    slots: Vec<Slot>,
+    len: usize,
+}
+
+impl TranspositionTable {
+    /// approximate bytes needed per stored node
+    pub(super) const BYTES_PER_ENTRY: usize = 2 * size_of::<Slot>();
+
+    pub(super) fn with_capacity(n_entries: usize) -> Self {
+        Self {
+            slots: vec![0; (2 * n_entries).next_power_of_two().max(2)],
+            len: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// bytes occupied by the slots once `additional` entries are inserted, they double when
+    /// more than half full
+    pub(super) fn memory_used_after_inserting(&self, additional: usize) -> usize {
+        let n_slots = if 2 * (self.len + additional) > self.slots.len() {
+            2 * self.slots.len()
+        } else {
+            self.slots.len()
+        };
+        n_slots * size_of::<Slot>()
+    }
+
+    pub(super) fn clear(&mut self) {
+        // fresh zeroed memory is cheaper than writing all slots, most were never touched
+        self.slots = vec![0; self.slots.len()];
+        self.len = 0;
+    }
+
+    const fn hash_check(zobrist: u64) -> u64 {
+        zobrist & !(NodeIdx::MAX as u64)
+    }
+
+    fn home_slot(&self, zobrist: u64) -> usize {
+        zobrist as usize & (self.slots.len() - 1)
+    }
+
+    pub(super) fn probe(
+        &self,
+        state: &NodeState,
+        state_of: impl Fn(NodeIdx) -> NodeState,
+    ) -> Probe {
+        let hash_check = Self::hash_check(state.zobrist());
+        let mut slot_idx = self.home_slot(state.zobrist());
+        loop {
+            let slot = self.slots[slot_idx];
+            let node = slot_node(slot);
+            if node == EMPTY {
+                return Probe::Vacant(slot_idx);
+            }
+            if Self::hash_check(slot) == hash_check && state_of(node) == *state {
+                return Probe::Found(node);
+            }
+            slot_idx = (slot_idx + 1) & (self.slots.len() - 1);
+        }
+    }
+
+    /// # Arguments
+    /// - `slot_idx`: returned by [`Self::probe`] for `state`, the table must not have been
+    ///   modified in between
+    pub(super) fn insert_vacant(
+        &mut self,
+        slot_idx: usize,
+        state: &NodeState,
+        node: NodeIdx,
+        state_of: impl Fn(NodeIdx) -> NodeState,
+    ) {
+        debug_assert_ne!(node, EMPTY);
+        debug_assert_eq!(slot_node(self.slots[slot_idx]), EMPTY);
+        self.slots[slot_idx] = Self::hash_check(state.zobrist()) | node as Slot;
+        self.len += 1;
+        if 2 * self.len > self.slots.len() {
+            self.grow(state_of);
+        }
+    }
+
+    /// inserts a state known to be absent
+    pub(super) fn insert_new(
+        &mut self,
+        state: &NodeState,
+        node: NodeIdx,
+        state_of: impl Fn(NodeIdx) -> NodeState,
+    ) {
+        match self.probe(state, &state_of) {
+            Probe::Vacant(slot_idx) => self.insert_vacant(slot_idx, state, node, state_of),
+            Probe::Found(_) => panic!("state must not be stored already"),
+        }
+    }
+
+    fn grow(&mut self, state_of: impl Fn(NodeIdx) -> NodeState) {
+        let new_slots = vec![0; 2 * self.slots.len()];
+        let old_slots = std::mem::replace(&mut self.slots, new_slots);
+        for slot in old_slots
+            .into_iter()
+            .filter(|slot| slot_node(*slot) != EMPTY)
+        {
+            let mut slot_idx = self.home_slot(state_of(slot_node(slot)).zobrist());
+            while slot_node(self.slots[slot_idx]) != EMPTY {
+                slot_idx = (slot_idx + 1) & (self.slots.len() - 1);
+            }
+            self.slots[slot_idx] = slot;
+        }
+    }
+}
+
+/// The positions of random games looked up in a [`TranspositionTable`] and in a `HashMap` from
+/// [`NodeState`] to [`NodeIdx`], for the micro-benchmarks in `benches/mcts.rs`.
+pub struct LookupBench {
+    /// in the order the games reached them, the early ones repeat like transpositions do
+    positions: Vec<NodeState>,
+    /// the distinct positions, indexed by [`NodeIdx`], nodes[0] is unused
+    nodes: Vec<NodeState>,
+    table: TranspositionTable,
+    hash_map: HashMap<NodeState, NodeIdx>,
+}
+
+impl LookupBench {
+    /// plays `n_games` random games from the empty board
+    pub fn new(n_games: usize, seed: u64) -> Self {
+        let mut rng = SearchRng::from_seed(seed);
+        let mut positions = Vec::new();
+        for _ in 0..n_games {
+            let mut state = NodeState::empty();
+            loop {
+                let available = state.available_in_board_or_fallback().get();
+                let (child_state, has_won) =
+                    state.apply_move(simulation::random_move(available, &mut rng));
+                if has_won || child_state.all_boards_decided() {
+                    break;
+                }
+                positions.push(child_state);
+                state = child_state;
+            }
+        }
+
+        let mut this = Self {
+            positions,
+            nodes: Vec::new(),
+            table: TranspositionTable::with_capacity(0),
+            hash_map: HashMap::new(),
+        };
+        (this.nodes, this.table) = this.table_get_or_insert_all();
+        this.hash_map = this.hash_map_get_or_insert_all();
+        this
+    }
+
+    pub fn n_positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// inserts the positions into a fresh table like the tree inserts new nodes, probing first
+    /// # Returns
+    /// the number of distinct positions
+    pub fn table_get_or_insert(&self) -> usize {
+        self.table_get_or_insert_all().1.len
+    }
+
+    /// [`Self::table_get_or_insert`] with a fresh `HashMap`
+    pub fn hash_map_get_or_insert(&self) -> usize {
+        self.hash_map_get_or_insert_all().len()
+    }
+
+    /// looks up every position in the filled table
+    /// # Returns
+    /// the sum of the found nodes
+    pub fn table_probe(&self) -> usize {
+        self.positions
+            .iter()
+            .map(
+                |state| match self.table.probe(state, |node| self.nodes[node as usize]) {
+                    Probe::Found(node) => node as usize,
+                    Probe::Vacant(_) => unreachable!("every position was inserted"),
+                },
+            )
+            .sum()
+    }
+
+    /// [`Self::table_probe`] in the filled `HashMap`
+    pub fn hash_map_probe(&self) -> usize {
+        self.positions
+            .iter()
+            .map(|state| self.hash_map[state] as usize)
+            .sum()
+    }
+
+    fn table_get_or_insert_all(&self) -> (Vec<NodeState>, TranspositionTable) {
+        let mut nodes = vec![NodeState::default()];
+        let mut table = TranspositionTable::with_capacity(self.positions.len());
+        for state in &self.positions {
+            if let Probe::Vacant(slot_idx) = table.probe(state, |node| nodes[node as usize]) {
+                nodes.push(*state);
+                let node = (nodes.len() - 1) as NodeIdx;
+                table.insert_vacant(slot_idx, state, node, |node| nodes[node as usize]);
+            }
+        }
+        (nodes, table)
+    }
+
+    fn hash_map_get_or_insert_all(&self) -> HashMap<NodeState, NodeIdx> {
+        let mut hash_map = HashMap::with_capacity(self.positions.len());
+        for state in &self.positions {
+            let next_node = hash_map.len() as NodeIdx + 1;
+            hash_map.entry(*state).or_insert(next_node);
+        }
+        hash_map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tree::{
+        node_state::NodeState,
+        transposition::{LookupBench, Probe, TranspositionTable},
+    };
+
+    /// a few thousand distinct states from a fixed sequence of moves, states[0] is unused
+    fn distinct_states() -> Vec<NodeState> {
+        let mut states = vec![NodeState::default()];
+        for first_move in 0..81 {
+            let (state, _) = NodeState::empty().apply_move(first_move);
+            states.push(state);
+            for second_move in (0..81).filter(|second_move| *second_move != first_move) {
+                states.push(state.apply_move(second_move).0);
+            }
+        }
+        states
+    }
+
+    #[test]
+    fn finds_all_inserted_states_while_growing() {
+        let states = distinct_states();
+        // tiny on purpose, to force collisions and several resizes
+        let mut table = TranspositionTable::with_capacity(1);
+        for (node, state) in states.iter().enumerate().skip(1) {
+            table.insert_new(state, node as u32, |node| states[node as usize]);
+        }
+        assert_eq!(table.len(), states.len() - 1);
+
+        for (node, state) in states.iter().enumerate().skip(1) {
+            assert_eq!(
+                table.probe(state, |node| states[node as usize]),
+                Probe::Found(node as u32)
+            );
+        }
+
+        table.clear();
+        assert_eq!(table.len(), 0);
+        assert!(matches!(
+            table.probe(&states[1], |node| states[node as usize]),
+            Probe::Vacant(_)
+        ));
+    }
+
+    #[test]
+    fn verifies_full_state() {
+        let states = distinct_states();
+        let mut table = TranspositionTable::with_capacity(16);
+        table.insert_new(&states[1], 1, |node| states[node as usize]);
+        // pretend node 1 holds a different state with the same hash, it must not be returned
+        assert!(matches!(
+            table.probe(&states[1], |_| states[2]),
+            Probe::Vacant(_)
+        ));
+    }
+
+    #[test]
+    fn lookup_bench_agrees_with_hash_map() {
+        let bench = LookupBench::new(100, 11);
+        let n_distinct = bench.table_get_or_insert();
+        assert_eq!(n_distinct, bench.hash_map_get_or_insert());
+        // the games share their first positions
+        assert!(n_distinct < bench.n_positions());
+        // both number the positions in the order they were first reached
+        assert_eq!(bench.table_probe(), bench.hash_map_probe());
+    }
+}
@@ -70,5 +70,6 @@ pub type Index = u32;
 pub type Move = (Index, Index);
 
 /// For 256bit AVX2 loading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(align(32))]
 pub struct Aligned32<T, const N: usize>(pub [T; N]);